@@ -1,14 +1,195 @@
-use heck::{ToShoutySnakeCase, ToUpperCamelCase};
+use heck::{ToSnakeCase, ToUpperCamelCase};
 use proc_macro::TokenStream;
-use quote::{format_ident, quote};
-use syn::{parse_macro_input, DeriveInput, Field, FieldsNamed, Ident, Type};
+use quote::{format_ident, quote, quote_spanned, ToTokens};
+use syn::{
+    ext::IdentExt, parse_macro_input, parse_quote, parse_quote_spanned, spanned::Spanned, Attribute,
+    DeriveInput, Field, Fields, GenericParam, Generics, Ident, Member, Path, Type, Variant,
+};
 
-fn is_fieldset(field: Field) -> bool {
-    field
-        .attrs
-        .iter()
-        .filter_map(|a| a.path().get_ident())
-        .any(|i| *i == format_ident!("fieldset"))
+#[derive(Clone, Default)]
+struct FieldOptions {
+    nested: bool,
+    name: Option<Ident>,
+    eq: Option<Path>,
+    epsilon: Option<syn::Expr>,
+    validate: Option<Path>,
+    layout: Option<Type>,
+}
+
+struct ModelOptions {
+    derives: Vec<Path>,
+    codec: bool,
+    serde: bool,
+    validate: Option<Path>,
+    perf_index: Option<Type>,
+}
+
+#[derive(Clone)]
+enum FieldAccess {
+    /// Field of a struct model.
+    Member(Member),
+    /// Field of an enum model variant, only reachable while that variant is active.
+    VariantMember(Ident, Member),
+    /// Variant switch of an enum model, carrying the whole new value.
+    Switch,
+}
+
+/// Array-backed `FieldSet` storage, which shares the derived methods of the others.
+#[derive(Clone, Copy)]
+enum Storage {
+    Bit,
+    Perf,
+    Index,
+}
+
+impl Storage {
+    fn fieldset_identifier(self, name: &str) -> Ident {
+        match self {
+            Storage::Bit => format_ident!("{}BitFieldSet", name),
+            Storage::Perf => format_ident!("{}PerfFieldSet", name),
+            Storage::Index => format_ident!("{}IndexFieldSet", name),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct TrackedField {
+    access: FieldAccess,
+    method_name: Ident,
+    variant_name: Ident,
+    ty: Type,
+    is_fieldset: bool,
+    index_expr: proc_macro2::TokenStream,
+    eq: Option<Path>,
+    epsilon: Option<syn::Expr>,
+    validate: Option<Path>,
+    layout: Option<Type>,
+}
+
+#[derive(Default)]
+struct FieldLayout {
+    fields: Vec<TrackedField>,
+    variances: Vec<proc_macro2::TokenStream>,
+    field_count: usize,
+}
+
+impl FieldLayout {
+    fn push(
+        &mut self,
+        access: FieldAccess,
+        method_name: Ident,
+        variant_name: Ident,
+        ty: Type,
+        options: FieldOptions,
+    ) {
+        let variances = &self.variances;
+        let field_count = self.field_count;
+        let is_fieldset = options.nested;
+        self.fields.push(TrackedField {
+            access,
+            method_name,
+            variant_name,
+            ty: ty.clone(),
+            is_fieldset,
+            index_expr: quote!(#( #variances +)* #field_count),
+            eq: options.eq,
+            epsilon: options.epsilon,
+            validate: options.validate,
+            layout: options.layout,
+        });
+        if is_fieldset {
+            self.variances.push(get_nested_variance(ty));
+        } else {
+            self.field_count += 1;
+        }
+    }
+}
+
+fn get_field_options(field: Field) -> syn::Result<FieldOptions> {
+    let mut options = FieldOptions::default();
+    // First option that only applies to fields that are not nested models, for the error span.
+    let mut leaf_option = None;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("fieldset")) {
+        if let syn::Meta::Path(_) = attr.meta {
+            options.nested = true;
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("nested") {
+                options.nested = true;
+                Ok(())
+            } else if meta.path.is_ident("name") {
+                options.name = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("eq") {
+                options.eq = Some(meta.value()?.parse()?);
+                leaf_option.get_or_insert(meta.path);
+                Ok(())
+            } else if meta.path.is_ident("epsilon") {
+                options.epsilon = Some(meta.value()?.parse()?);
+                leaf_option.get_or_insert(meta.path);
+                Ok(())
+            } else if meta.path.is_ident("validate") {
+                options.validate = Some(meta.value()?.parse()?);
+                leaf_option.get_or_insert(meta.path);
+                Ok(())
+            } else if meta.path.is_ident("layout") {
+                options.layout = Some(meta.value()?.parse()?);
+                options.nested = true;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported fieldset field option"))
+            }
+        })?;
+    }
+    if let (true, Some(path)) = (options.nested, leaf_option) {
+        return Err(syn::Error::new_spanned(
+            path,
+            "`eq`, `epsilon` and `validate` only apply to fields that are not nested models",
+        ));
+    }
+    Ok(options)
+}
+
+/// Methods of the types that implement the setter traits, which would hide or clash with setters
+/// of the same name: the setter traits themselves, the derived `FieldSet` types, `LogFieldSet`,
+/// `SharedFieldSet`, the `Producer` of a `ChangeQueue` and the model.
+const RESERVED_METHOD_NAMES: &[&str] = &[
+    "apply",
+    "put",
+    "into_iter",
+    "iter",
+    "drain",
+    "clear",
+    "sort",
+    "len",
+    "is_empty",
+    "is_modified",
+    "is_any_modified",
+    "pending",
+    "push",
+    "check",
+    "dropped",
+    "capacity",
+    "take",
+    "with",
+    "try_apply_all",
+    "apply_with_undo",
+    "apply_with_inverse",
+];
+
+fn check_method_name(method_name: &Ident, field: &Field) -> syn::Result<()> {
+    if RESERVED_METHOD_NAMES.iter().any(|name| method_name == name) {
+        return Err(syn::Error::new_spanned(
+            field,
+            format!(
+                "the setter `{}` is hidden by a method of the types implementing the setter traits, rename it with `#[fieldset(name = ...)]`; reserved names: {}",
+                method_name,
+                RESERVED_METHOD_NAMES.join(", ")
+            ),
+        ));
+    }
+    Ok(())
 }
 
 fn is_skipped(field: Field) -> bool {
@@ -19,583 +200,2489 @@ fn is_skipped(field: Field) -> bool {
         .any(|i| *i == format_ident!("fieldset_skip"))
 }
 
-fn get_type_identifier(ty: Type) -> Ident {
-    match ty {
-        Type::Path(p) => {
-            assert!(p.clone().qself.is_none());
-            p.path
-                .get_ident()
-                .expect("field type must be a path with an identifier")
-                .clone()
+fn get_tracked_fields(fields: Fields) -> syn::Result<Vec<TrackedField>> {
+    let mut layout = FieldLayout::default();
+    for (position, field) in fields.into_iter().enumerate() {
+        if is_skipped(field.clone()) {
+            continue;
         }
-        _ => panic!("unsupported field type"),
+        let options = get_field_options(field.clone())?;
+        let member = match field.ident.clone() {
+            Some(ident) => Member::Named(ident),
+            None => Member::Unnamed(position.into()),
+        };
+        let (method_name, variant_name) = match options.name.clone().or(field.ident.clone()) {
+            Some(ident) => (
+                ident.clone(),
+                format_ident!("{}", ident.to_string().to_upper_camel_case()),
+            ),
+            None => (
+                format_ident!("_{}", position),
+                format_ident!("_{}", position),
+            ),
+        };
+        check_method_name(&method_name, &field)?;
+        layout.push(
+            FieldAccess::Member(member),
+            method_name,
+            variant_name,
+            field.ty,
+            options,
+        );
     }
+    Ok(layout.fields)
 }
 
-fn get_field_identifier(field: Field) -> Ident {
-    field
-        .ident
-        .expect("Cannot derive field type from tuple structs")
+fn get_tracked_variant_fields(
+    name: String,
+    generics: Generics,
+    variants: Vec<Variant>,
+) -> syn::Result<Vec<TrackedField>> {
+    let identifier = format_ident!("{}", name);
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let mut layout = FieldLayout::default();
+    layout.push(
+        FieldAccess::Switch,
+        format_ident!("variant"),
+        format_ident!("Variant"),
+        parse_quote!(#identifier #ty_generics),
+        FieldOptions::default(),
+    );
+    for variant in variants {
+        let prefix = variant.ident.to_string();
+        for (position, field) in variant.fields.into_iter().enumerate() {
+            if is_skipped(field.clone()) {
+                continue;
+            }
+            let options = get_field_options(field.clone())?;
+            let member = match field.ident.clone() {
+                Some(ident) => Member::Named(ident),
+                None => Member::Unnamed(position.into()),
+            };
+            let (method_name, variant_name) = match (options.name.clone(), field.ident.clone()) {
+                (Some(ident), _) => (
+                    ident.clone(),
+                    format_ident!("{}", ident.to_string().to_upper_camel_case()),
+                ),
+                (None, Some(ident)) => (
+                    format_ident!("{}_{}", prefix.to_snake_case(), ident),
+                    format_ident!("{}{}", prefix, ident.to_string().to_upper_camel_case()),
+                ),
+                (None, None) => (
+                    format_ident!("{}_{}", prefix.to_snake_case(), position),
+                    format_ident!("{}{}", prefix, position),
+                ),
+            };
+            check_method_name(&method_name, &field)?;
+            layout.push(
+                FieldAccess::VariantMember(variant.ident.clone(), member),
+                method_name,
+                variant_name,
+                field.ty,
+                options,
+            );
+        }
+    }
+    Ok(layout.fields)
+}
+
+fn get_model_options(attrs: Vec<Attribute>) -> syn::Result<ModelOptions> {
+    let mut derives = None;
+    let mut codec = false;
+    let mut serde = false;
+    let mut validate = None;
+    let mut perf_index = None;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("fieldset")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("derive") {
+                let mut paths = Vec::new();
+                meta.parse_nested_meta(|meta| {
+                    paths.push(meta.path);
+                    Ok(())
+                })?;
+                derives = Some(paths);
+                Ok(())
+            } else if meta.path.is_ident("codec") {
+                codec = true;
+                Ok(())
+            } else if meta.path.is_ident("serde") {
+                if !cfg!(feature = "serde") {
+                    return Err(meta.error("`serde` requires the `serde` feature of `fieldset`"));
+                }
+                serde = true;
+                Ok(())
+            } else if meta.path.is_ident("validate") {
+                validate = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("perf_index") {
+                perf_index = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported fieldset option"))
+            }
+        })?;
+    }
+    Ok(ModelOptions {
+        derives: derives.unwrap_or_else(|| {
+            vec![
+                parse_quote!(Clone),
+                parse_quote!(Copy),
+                parse_quote!(Debug),
+                parse_quote!(PartialEq),
+            ]
+        }),
+        codec,
+        serde,
+        validate,
+        perf_index,
+    })
+}
+
+fn is_derived(derive: &Path, name: &str) -> bool {
+    derive.segments.last().is_some_and(|s| s.ident == name)
+}
+
+/// Keeps the derives of the `FieldType` that the derived storages and iterators can forward.
+fn get_forwarded_derives(derives: Vec<Path>, forwarded: &[&str]) -> proc_macro2::TokenStream {
+    let derives: Vec<_> = derives
+        .into_iter()
+        .filter(|p| forwarded.iter().any(|f| is_derived(p, f)))
+        .collect();
+    if derives.is_empty() {
+        quote!()
+    } else {
+        quote!(#[derive(#( #derives ),*)])
+    }
+}
+
+fn depends_on_generics(ty: Type, generics: Generics) -> bool {
+    fn visit(tokens: proc_macro2::TokenStream, params: &[String]) -> bool {
+        tokens.into_iter().any(|token| match token {
+            proc_macro2::TokenTree::Ident(i) => params.contains(&i.to_string()),
+            proc_macro2::TokenTree::Group(g) => visit(g.stream(), params),
+            _ => false,
+        })
+    }
+    let params: Vec<_> = generics
+        .params
+        .iter()
+        .map(|p| match p {
+            GenericParam::Type(t) => t.ident.to_string(),
+            GenericParam::Lifetime(l) => l.lifetime.ident.to_string(),
+            GenericParam::Const(c) => c.ident.to_string(),
+        })
+        .collect();
+    visit(ty.into_token_stream(), &params)
 }
 
-fn derive_field_type(name: String, fields: FieldsNamed) -> TokenStream {
+fn derive_field_type(
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+    derives: Vec<Path>,
+) -> TokenStream {
     let derived_field_type_identifier = format_ident!("{}FieldType", name);
+    let where_clause = generics.where_clause.clone();
     let enum_variants = {
         let mut res = Vec::new();
-        for field in fields.named {
-            let variant_name = format_ident!(
-                "{}",
-                get_field_identifier(field.clone())
-                    .to_string()
-                    .to_upper_camel_case()
-            );
-            if is_skipped(field.clone()) {
-                continue;
-            }
-            if is_fieldset(field.clone()) {
-                let type_identifier = get_type_identifier(field.ty);
-                let field_type_identifier = format_ident!("{}FieldType", type_identifier);
-                res.push(quote!(#variant_name(#field_type_identifier)));
+        for field in fields {
+            let variant_name = field.variant_name;
+            let ty = field.ty;
+            if field.is_fieldset {
+                res.push(quote!(#variant_name(<#ty as fieldset::FieldSetModel>::FieldType)));
             } else {
-                let ty = field.ty;
                 res.push(quote!(#variant_name(#ty)));
             }
         }
         res
     };
     quote!(
-        #[derive(Clone, Copy, Debug, PartialEq)]
-        pub enum #derived_field_type_identifier {
+        #[derive(#( #derives ),*)]
+        pub enum #derived_field_type_identifier #generics #where_clause {
             #(#enum_variants ,)*
         }
     )
     .into()
 }
 
-fn derive_into_iterator(name: String, fields: FieldsNamed) -> TokenStream {
+fn derive_into_iterator(
+    name: String,
+    generics: Generics,
+    _fields: Vec<TrackedField>,
+) -> TokenStream {
     let identifier = format_ident!("{}", name);
     let fieldtype_identifier = format_ident!("{}FieldType", name);
-    let iter_chains = {
-        let mut res = Vec::new();
-        for field in fields.named {
-            let field_identifier = get_field_identifier(field.clone());
-            let variant_name = format_ident!(
-                "{}",
-                field_identifier.clone().to_string().to_upper_camel_case()
-            );
-            if is_skipped(field.clone()) {
-                continue;
-            }
-            if is_fieldset(field.clone()) {
-                res.push(quote!(let iter = iter.chain(self.#field_identifier.into_iter().map(#fieldtype_identifier::#variant_name))));
-            } else {
-                res.push(quote!(let iter = iter.chain(once(#fieldtype_identifier::#variant_name(self.#field_identifier)))));
-            }
-        }
-        res
-    };
+    let fieldset_identifier = format_ident!("{}OptFieldSet", name);
+    let iter_identifier = format_ident!("{}OptFieldSetIter", name);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
     quote!(
-        impl IntoIterator for #identifier {
-            type Item = #fieldtype_identifier;
-            type IntoIter = impl Iterator<Item = Self::Item> + Clone + core::fmt::Debug;
+        impl #impl_generics IntoIterator for #identifier #ty_generics #where_clause {
+            type Item = #fieldtype_identifier #ty_generics;
+            type IntoIter = #iter_identifier #ty_generics;
 
             fn into_iter(self) -> Self::IntoIter {
-                use core::iter::empty;
-                use core::iter::once;
-                let iter = empty();
-
-                #( #iter_chains ;)*
-
-                iter
+                #fieldset_identifier::from(self).into_iter()
             }
         }
     )
     .into()
 }
 
-fn derive_setter_trait(name: String, fields: FieldsNamed) -> TokenStream {
+/// Closure returning the payload of a `FieldType` of the `variant_name` variant.
+fn get_unwrap(fieldtype: proc_macro2::TokenStream, variant_name: &Ident) -> proc_macro2::TokenStream {
+    quote!(|x| match x {
+        #fieldtype::#variant_name(x) => Some(x),
+        // Models with a single field leave the fallback arm unreachable.
+        #[allow(unreachable_patterns)]
+        _ => None,
+    })
+}
+
+fn derive_setter_trait(name: String, generics: Generics, fields: Vec<TrackedField>) -> TokenStream {
+    let identifier = format_ident!("{}", name);
     let derived_setter_trait_identifier = format_ident!("{}FieldSetter", name);
     let field_type_identifier = format_ident!("{}FieldType", name);
+    let (_, ty_generics, where_clause) = generics.split_for_impl();
+    let field_type = quote!(#field_type_identifier #ty_generics);
     let methods = {
         let mut res = Vec::new();
-        for field in fields.clone().named {
-            let method_name = get_field_identifier(field.clone());
-            if is_skipped(field.clone()) {
-                continue;
-            }
-            if is_fieldset(field.clone()) {
-                let type_identifier = get_type_identifier(field.ty);
-                let field_setter_trait_identifier = format_ident!("{}FieldSetter", type_identifier);
-                res.push(quote!(fn #method_name(&mut self) -> impl #field_setter_trait_identifier));
-            } else {
-                let ty = field.ty;
-                res.push(quote!(fn #method_name(&mut self) -> impl fieldset::FieldSetter<#ty>));
-            }
-        }
-        res
-    };
-    let match_arms = {
-        let mut res = Vec::new();
-        for field in fields.named {
-            let field_identifier = get_field_identifier(field.clone());
-            let variant_name = format_ident!(
-                "{}",
-                field_identifier.clone().to_string().to_upper_camel_case()
-            );
-            if is_skipped(field.clone()) {
-                continue;
-            }
-            if is_fieldset(field.clone()) {
-                res.push(quote!(#field_type_identifier::#variant_name(x) => self.#field_identifier().apply(x)));
+        for field in fields {
+            let method_name = field.method_name;
+            let variant_name = field.variant_name;
+            let index_expr = field.index_expr;
+            let ty = field.ty;
+            let unwrap = get_unwrap(quote!(#field_type_identifier), &variant_name);
+            if field.is_fieldset {
+                res.push(quote!(
+                    fn #method_name(&mut self) -> fieldset::NestedFieldSetter<'_, Self, #field_type, <#ty as fieldset::FieldSetModel>::FieldType> {
+                        fieldset::NestedFieldSetter::new(self, #index_expr, #field_type_identifier::#variant_name, #unwrap)
+                    }
+                ));
             } else {
-                res.push(
-                    quote!(#field_type_identifier::#variant_name(x) => self.#field_identifier().set(x)),
-                );
+                res.push(quote!(
+                    fn #method_name(&mut self) -> fieldset::LeafFieldSetter<'_, Self, #field_type, #ty> {
+                        fieldset::LeafFieldSetter::new(self, #index_expr, #field_type_identifier::#variant_name, #unwrap)
+                    }
+                ));
             }
         }
         res
     };
+    let mut blanket_generics = generics.clone();
+    blanket_generics
+        .params
+        .push(parse_quote!(__S: fieldset::FieldSink<#field_type> + ?Sized));
+    let (blanket_impl_generics, _, blanket_where_clause) = blanket_generics.split_for_impl();
 
     quote!(
-        pub trait #derived_setter_trait_identifier {
-            #( #methods ;)*
+        pub trait #derived_setter_trait_identifier #generics : fieldset::FieldSink<#field_type> #where_clause {
+            #( #methods )*
 
-            fn apply(&mut self, field: #field_type_identifier) {
-                match field {
-                    #( #match_arms ,)*
-                }
+            fn apply(&mut self, field: #field_type) {
+                let index = <#identifier #ty_generics as fieldset::FieldSetModel>::field_index(&field);
+                self.put(index, field);
             }
         }
+
+        impl #blanket_impl_generics #derived_setter_trait_identifier #ty_generics for __S #blanket_where_clause {}
     )
     .into()
 }
 
-fn get_variance_identifier(ty: Ident) -> Ident {
-    format_ident!("{}_VARIANCE", ty.to_string().to_shouty_snake_case())
+/// `ty` with the generic parameters of the model replaced by placeholders, `()` for types,
+/// `'static` for lifetimes and zero for constants, for array lengths, which cannot depend on
+/// generic parameters. The layout of a model does not depend on its parameters, so a nested model
+/// erased this way has the same variance and fields, provided that `()` satisfies its bounds.
+fn erase_generics(ty: Type, generics: Generics) -> proc_macro2::TokenStream {
+    fn visit(tokens: proc_macro2::TokenStream, generics: &Generics) -> proc_macro2::TokenStream {
+        let mut is_lifetime = false;
+        let mut res = proc_macro2::TokenStream::new();
+        for token in tokens {
+            match token {
+                proc_macro2::TokenTree::Ident(i) => {
+                    let param = generics.params.iter().find(|p| match p {
+                        GenericParam::Type(t) => !is_lifetime && t.ident == i,
+                        GenericParam::Lifetime(l) => is_lifetime && l.lifetime.ident == i,
+                        GenericParam::Const(c) => !is_lifetime && c.ident == i,
+                    });
+                    match param {
+                        // Spanned at the parameter, so that unmet bounds point at the field.
+                        Some(GenericParam::Type(_)) => res.extend(quote_spanned!(i.span()=> ())),
+                        Some(GenericParam::Lifetime(_)) => res.extend(quote!(static)),
+                        Some(GenericParam::Const(c)) => {
+                            let value = match c.ty.to_token_stream().to_string().as_str() {
+                                "bool" => quote!(false),
+                                "char" => quote!('\0'),
+                                _ => quote!(0),
+                            };
+                            res.extend(quote!({ #value }));
+                        }
+                        None => res.extend([proc_macro2::TokenTree::Ident(i)]),
+                    }
+                    is_lifetime = false;
+                }
+                proc_macro2::TokenTree::Group(g) => {
+                    let mut group =
+                        proc_macro2::Group::new(g.delimiter(), visit(g.stream(), generics));
+                    group.set_span(g.span());
+                    res.extend([proc_macro2::TokenTree::Group(group)]);
+                    is_lifetime = false;
+                }
+                proc_macro2::TokenTree::Punct(p) => {
+                    is_lifetime = p.as_char() == '\'';
+                    res.extend([proc_macro2::TokenTree::Punct(p)]);
+                }
+                token => {
+                    res.extend([token]);
+                    is_lifetime = false;
+                }
+            }
+        }
+        res
+    }
+    visit(ty.into_token_stream(), &generics)
+}
+
+fn get_nested_variance(ty: Type) -> proc_macro2::TokenStream {
+    quote!(<#ty as fieldset::FieldSetModel>::VARIANCE)
+}
+
+/// Instance of the nested model of `field` through which array lengths reach its layout, the one
+/// given by `#[fieldset(layout = ...)]` or else its erased type.
+fn get_layout_type(field: TrackedField, generics: Generics) -> proc_macro2::TokenStream {
+    match field.layout {
+        Some(layout) => quote!(#layout),
+        None => erase_generics(field.ty, generics),
+    }
+}
+
+/// Variance of the nested model of `field` for array lengths, reached through its layout type.
+fn get_nested_layout_variance(field: TrackedField, generics: Generics) -> proc_macro2::TokenStream {
+    let ty = get_layout_type(field, generics);
+    quote!(<#ty as fieldset::FieldSetModel>::VARIANCE)
 }
 
-fn derive_fieldset_variance(name: String, fields: FieldsNamed) -> TokenStream {
+/// Variance of the model for the array lengths of its storages.
+fn get_layout_variance(generics: Generics, fields: Vec<TrackedField>) -> proc_macro2::TokenStream {
+    let mut variances = Vec::new();
+    let mut field_count: usize = 0;
+    for field in fields {
+        if field.is_fieldset {
+            variances.push(get_nested_layout_variance(field, generics.clone()));
+        } else {
+            field_count += 1;
+        }
+    }
+    quote!({ #( #variances +)* #field_count })
+}
+
+/// `match` over a borrowed `FieldType`. Models without tracked fields have an uninhabited
+/// `FieldType`, which is matched through the dereferenced place, as an empty `match` on the
+/// reference itself is not exhaustive.
+fn match_borrowed(
+    scrutinee: proc_macro2::TokenStream,
+    arms: Vec<proc_macro2::TokenStream>,
+) -> proc_macro2::TokenStream {
+    if arms.is_empty() {
+        quote!(match *#scrutinee {})
+    } else {
+        quote!(match #scrutinee { #( #arms ,)* })
+    }
+}
+
+fn derive_fieldset_model(
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+) -> TokenStream {
     let identifier = format_ident!("{}", name);
-    let variance_identifier = get_variance_identifier(identifier);
+    let fieldtype_identifier = format_ident!("{}FieldType", name);
+    let fieldset_identifier = format_ident!("{}OptFieldSet", name);
+    let field_id_identifier = format_ident!("{}FieldId", name);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
     let variance = {
         let mut variances = Vec::new();
         let mut field_count: usize = 0;
-        for field in fields.named {
-            if is_skipped(field.clone()) {
-                continue;
-            }
-            if is_fieldset(field.clone()) {
-                let type_identifier = get_type_identifier(field.ty);
-                let variance_identifier = get_variance_identifier(type_identifier);
-                variances.push(quote!(#variance_identifier));
+        for field in fields.clone() {
+            if field.is_fieldset {
+                variances.push(get_nested_variance(field.ty));
             } else {
                 field_count += 1;
             }
         }
         quote!(#( #variances +)* #field_count)
     };
+    let match_arms = {
+        let mut res = Vec::new();
+        for field in fields.clone() {
+            let variant_name = field.variant_name;
+            let index_expr = field.index_expr;
+            let ty = field.ty;
+            if field.is_fieldset {
+                res.push(quote!(#fieldtype_identifier::#variant_name(x) => #index_expr + <#ty as fieldset::FieldSetModel>::field_index(x)));
+            } else {
+                res.push(quote!(#fieldtype_identifier::#variant_name(_) => #index_expr));
+            }
+        }
+        res
+    };
+    let superseded_arms = {
+        let mut res = Vec::new();
+        for field in fields.clone() {
+            let variant_name = field.variant_name;
+            let index_expr = field.index_expr;
+            let ty = field.ty;
+            if field.is_fieldset {
+                res.push(quote!(#fieldtype_identifier::#variant_name(x) => {
+                    let superseded = <#ty as fieldset::FieldSetModel>::superseded_fields(x);
+                    (#index_expr + superseded.start)..(#index_expr + superseded.end)
+                }));
+            } else if let FieldAccess::Switch = field.access {
+                res.push(quote!(#fieldtype_identifier::#variant_name(_) => 1..Self::VARIANCE));
+            } else {
+                res.push(quote!(#fieldtype_identifier::#variant_name(_) => 0..0));
+            }
+        }
+        res
+    };
+    let match_arms = match_borrowed(quote!(field), match_arms);
+    let superseded_arms = match_borrowed(quote!(field), superseded_arms);
     quote!(
-        const #variance_identifier : usize = #variance;
+        impl #impl_generics fieldset::FieldSetModel for #identifier #ty_generics #where_clause {
+            type FieldType = #fieldtype_identifier #ty_generics;
+            type FieldId = #field_id_identifier #ty_generics;
+            type OptFieldSet = #fieldset_identifier #ty_generics;
+
+            const VARIANCE: usize = #variance;
+
+            const FIELDS: &'static [fieldset::FieldInfo] = &<#identifier #ty_generics>::FIELDS;
+
+            fn field_index(field: &Self::FieldType) -> usize {
+                #match_arms
+            }
+
+            fn superseded_fields(field: &Self::FieldType) -> core::ops::Range<usize> {
+                #superseded_arms
+            }
+        }
     )
     .into()
 }
 
-fn derive_raw_fieldset_setter_trait_impl(name: String, fields: FieldsNamed) -> TokenStream {
+fn derive_raw_fieldset_sink_impl(
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+) -> TokenStream {
     let identifier = format_ident!("{}", name);
-    let setter_trait_identifier = format_ident!("{}FieldSetter", name);
-    let methods = {
+    let fieldtype_identifier = format_ident!("{}FieldType", name);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let match_arms = {
         let mut res = Vec::new();
-        for field in fields.named {
-            let field_name = get_field_identifier(field.clone());
-            let method_name = field_name.clone();
-            if is_skipped(field.clone()) {
-                continue;
-            }
-            if is_fieldset(field.clone()) {
-                let type_identifier = get_type_identifier(field.ty);
-                let field_setter_trait_identifier = format_ident!("{}FieldSetter", type_identifier);
-                res.push(quote!(fn #method_name(&mut self) -> impl #field_setter_trait_identifier { &mut self.#field_name }));
+        for field in fields {
+            let variant_name = field.variant_name;
+            let index_expr = field.index_expr;
+            let assignment = if field.is_fieldset {
+                quote!(fieldset::FieldSink::put(target, index - (#index_expr), x))
             } else {
-                let ty = field.ty;
-                res.push(
-                    quote!(fn #method_name(&mut self) -> impl fieldset::FieldSetter<#ty> { fieldset::RawFieldSetter(&mut self.#field_name) }),
-                );
+                quote!(*target = x)
+            };
+            match field.access {
+                FieldAccess::Member(member) => {
+                    res.push(quote!(#fieldtype_identifier::#variant_name(x) => { let target = &mut self.#member; #assignment }));
+                }
+                // Modifications of an inactive variant no longer apply and are dropped.
+                FieldAccess::VariantMember(variant, member) => {
+                    res.push(quote!(#fieldtype_identifier::#variant_name(x) => if let Self::#variant { #member: target, .. } = self { #assignment }));
+                }
+                FieldAccess::Switch => {
+                    res.push(quote!(#fieldtype_identifier::#variant_name(x) => *self = x));
+                }
             }
         }
         res
     };
 
     quote!(
-        impl #setter_trait_identifier for &mut #identifier {
-            #( #methods )*
-        }
-
-        impl #setter_trait_identifier for #identifier {
-            #( #methods )*
+        impl #impl_generics fieldset::FieldSink<#fieldtype_identifier #ty_generics> for #identifier #ty_generics #where_clause {
+            fn put(&mut self, index: usize, value: #fieldtype_identifier #ty_generics) {
+                let _ = index;
+                match value {
+                    #( #match_arms ,)*
+                }
+            }
         }
     )
     .into()
 }
 
-fn derive_opt_fieldset_type(name: String, fields: FieldsNamed) -> TokenStream {
+fn derive_opt_fieldset_type(
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+    derives: Vec<Path>,
+) -> TokenStream {
     let derived_fieldset_identifier = format_ident!("{}OptFieldSet", name);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let mut field_identifiers = Vec::new();
     let opt_fields = {
         let mut res = Vec::new();
-        for field in fields.named {
-            let field_identifier = get_field_identifier(field.clone());
-            if is_skipped(field.clone()) {
-                continue;
-            }
-            if is_fieldset(field.clone()) {
-                let type_identifier = get_type_identifier(field.ty);
-                let fieldset_identifier = format_ident!("{}OptFieldSet", type_identifier);
-                res.push(quote!(#field_identifier : #fieldset_identifier));
+        for field in fields {
+            let field_identifier = field.method_name;
+            field_identifiers.push(field_identifier.clone());
+            let ty = field.ty;
+            if field.is_fieldset {
+                res.push(quote!(#field_identifier : <#ty as fieldset::FieldSetModel>::OptFieldSet));
             } else {
-                let ty = field.ty;
                 res.push(quote!(#field_identifier : Option<#ty>))
             }
         }
         res
     };
+    let derive_debug = get_forwarded_derives(derives, &["Debug"]);
     quote!(
-        #[derive(Debug, Default)]
-        pub struct #derived_fieldset_identifier {
+        #derive_debug
+        pub struct #derived_fieldset_identifier #generics #where_clause {
             #(#opt_fields ,)*
         }
 
-        impl #derived_fieldset_identifier {
+        impl #impl_generics #derived_fieldset_identifier #ty_generics #where_clause {
             pub fn new() -> Self {
                 Default::default()
             }
         }
+
+        impl #impl_generics Default for #derived_fieldset_identifier #ty_generics #where_clause {
+            fn default() -> Self {
+                Self {
+                    #(#field_identifiers : Default::default() ,)*
+                }
+            }
+        }
     )
     .into()
 }
 
-fn derive_opt_fieldset_setter_trait_impl(name: String, fields: FieldsNamed) -> TokenStream {
-    let setter_trait_identifier = format_ident!("{}FieldSetter", name);
+fn derive_opt_fieldset_sink_impl(
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+) -> TokenStream {
     let fieldset_identifier = format_ident!("{}OptFieldSet", name);
-    let methods = {
+    let fieldtype_identifier = format_ident!("{}FieldType", name);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let match_arms = {
         let mut res = Vec::new();
-        for field in fields.named {
-            let field_name = get_field_identifier(field.clone());
-            let method_name = field_name.clone();
-            if is_skipped(field.clone()) {
-                continue;
-            }
-            if is_fieldset(field.clone()) {
-                let type_identifier = get_type_identifier(field.ty);
-                let field_setter_trait_identifier = format_ident!("{}FieldSetter", type_identifier);
-                res.push(quote!(fn #method_name(&mut self) -> impl #field_setter_trait_identifier { &mut self.#field_name }));
+        for field in fields {
+            let field_identifier = field.method_name;
+            let variant_name = field.variant_name;
+            let index_expr = field.index_expr;
+            if field.is_fieldset {
+                res.push(quote!(#fieldtype_identifier::#variant_name(x) => fieldset::FieldSink::put(&mut self.#field_identifier, index - (#index_expr), x)));
+            } else if let FieldAccess::Switch = field.access {
+                res.push(quote!(#fieldtype_identifier::#variant_name(x) => *self = Self { #field_identifier: Some(x), ..Default::default() }));
             } else {
-                let ty = field.ty;
-                res.push(
-                    quote!(fn #method_name(&mut self) -> impl fieldset::FieldSetter<#ty> { fieldset::OptFieldSetter(&mut self.#field_name) }),
-                );
+                res.push(quote!(#fieldtype_identifier::#variant_name(x) => self.#field_identifier = Some(x)));
             }
         }
         res
     };
 
     quote!(
-        impl #setter_trait_identifier for &mut #fieldset_identifier {
-            #( #methods )*
-        }
-
-        impl #setter_trait_identifier for #fieldset_identifier {
-            #( #methods )*
+        impl #impl_generics fieldset::FieldSink<#fieldtype_identifier #ty_generics> for #fieldset_identifier #ty_generics #where_clause {
+            fn put(&mut self, index: usize, value: #fieldtype_identifier #ty_generics) {
+                let _ = index;
+                match value {
+                    #( #match_arms ,)*
+                }
+            }
         }
     )
     .into()
 }
 
-fn derive_opt_fieldset_into_iterator(name: String, fields: FieldsNamed) -> TokenStream {
+fn derive_opt_fieldset_from_model(
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+) -> TokenStream {
+    let identifier = format_ident!("{}", name);
     let fieldset_identifier = format_ident!("{}OptFieldSet", name);
-    let fieldtype_identifier = format_ident!("{}FieldType", name);
-    let iter_chains = {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let mut is_enum = false;
+    let field_values = {
         let mut res = Vec::new();
-        for field in fields.named {
-            let field_identifier = get_field_identifier(field.clone());
-            let variant_name = format_ident!(
-                "{}",
-                field_identifier.clone().to_string().to_upper_camel_case()
-            );
-            if is_skipped(field.clone()) {
-                continue;
-            }
-            if is_fieldset(field.clone()) {
-                res.push(quote!(let iter = iter.chain(self.#field_identifier.opt_iter().map(|x| x.map(#fieldtype_identifier::#variant_name)))));
-            } else {
-                res.push(quote!(let iter = iter.chain(once(self.#field_identifier.map(#fieldtype_identifier::#variant_name)))));
+        for field in fields {
+            let field_identifier = field.method_name;
+            match field.access {
+                FieldAccess::Member(member) if field.is_fieldset => {
+                    res.push(quote!(#field_identifier : model.#member.into()));
+                }
+                FieldAccess::Member(member) => {
+                    res.push(quote!(#field_identifier : Some(model.#member)));
+                }
+                // The variant switch carries the whole value, so the fields of the active variant
+                // need not be listed separately.
+                FieldAccess::Switch => {
+                    is_enum = true;
+                    res.push(quote!(#field_identifier : Some(model)));
+                }
+                FieldAccess::VariantMember(..) => {}
             }
         }
         res
     };
+    let rest = if is_enum {
+        quote!(..Default::default())
+    } else {
+        quote!()
+    };
+    quote!(
+        impl #impl_generics From<#identifier #ty_generics> for #fieldset_identifier #ty_generics #where_clause {
+            fn from(model: #identifier #ty_generics) -> Self {
+                Self {
+                    #( #field_values ,)*
+                    #rest
+                }
+            }
+        }
+    )
+    .into()
+}
+
+fn derive_opt_fieldset_into_iterator(
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+    derives: Vec<Path>,
+) -> TokenStream {
+    let fieldset_identifier = format_ident!("{}OptFieldSet", name);
+    let fieldtype_identifier = format_ident!("{}FieldType", name);
+    let iter_identifier = format_ident!("{}OptFieldSetIter", name);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let mut field_identifiers = Vec::new();
+    let mut iter_fields = Vec::new();
+    let mut iter_values = Vec::new();
+    let mut next_steps = Vec::new();
+    for field in fields {
+        let field_identifier = field.method_name;
+        let variant_name = field.variant_name;
+        let ty = field.ty;
+        field_identifiers.push(field_identifier.clone());
+        if field.is_fieldset {
+            iter_fields.push(quote!(#field_identifier : <<#ty as fieldset::FieldSetModel>::OptFieldSet as IntoIterator>::IntoIter));
+            iter_values.push(quote!(self.#field_identifier.into_iter()));
+            next_steps.push(quote!(
+                if let Some(x) = self.#field_identifier.next() {
+                    return Some(#fieldtype_identifier::#variant_name(x));
+                }
+            ));
+        } else {
+            iter_fields.push(quote!(#field_identifier : Option<#ty>));
+            iter_values.push(quote!(self.#field_identifier));
+            next_steps.push(quote!(
+                if let Some(x) = self.#field_identifier.take() {
+                    return Some(#fieldtype_identifier::#variant_name(x));
+                }
+            ));
+        }
+    }
+    let derive_clone_debug = get_forwarded_derives(derives, &["Clone", "Debug"]);
     quote!(
-        impl #fieldset_identifier {
-            fn opt_iter(self) -> impl Iterator<Item = Option<#fieldtype_identifier>> + Clone + core::fmt::Debug {
-                use core::iter::empty;
-                use core::iter::once;
-                let iter = empty();
+        #derive_clone_debug
+        pub struct #iter_identifier #generics #where_clause {
+            #( #iter_fields ,)*
+        }
 
-                #( #iter_chains ;)*
+        impl #impl_generics Iterator for #iter_identifier #ty_generics #where_clause {
+            type Item = #fieldtype_identifier #ty_generics;
 
-                iter
+            fn next(&mut self) -> Option<Self::Item> {
+                #( #next_steps )*
+                None
             }
         }
 
-        impl IntoIterator for #fieldset_identifier {
-            type Item = #fieldtype_identifier;
-            type IntoIter = impl Iterator<Item = Self::Item> + Clone + core::fmt::Debug;
+        impl #impl_generics IntoIterator for #fieldset_identifier #ty_generics #where_clause {
+            type Item = #fieldtype_identifier #ty_generics;
+            type IntoIter = #iter_identifier #ty_generics;
 
             fn into_iter(self) -> Self::IntoIter {
-                self.opt_iter().flatten()
+                #iter_identifier {
+                    #( #field_identifiers : #iter_values ,)*
+                }
             }
         }
     )
     .into()
 }
 
-fn common_trait_impl_methods(
-    bitset_expr: proc_macro2::TokenStream,
-    fields_expr: proc_macro2::TokenStream,
-    len_expr: proc_macro2::TokenStream,
-    fun_expr: proc_macro2::TokenStream,
-    is_bitset: bool,
+fn derive_common_fieldset_sink_impl(
+    storage: Storage,
     name: String,
-    fields: FieldsNamed,
-) -> proc_macro2::TokenStream {
+    generics: Generics,
+    _fields: Vec<TrackedField>,
+) -> TokenStream {
+    let fieldset_identifier = storage.fieldset_identifier(&name);
     let fieldtype_identifier = format_ident!("{}FieldType", name);
-    let mut res = Vec::new();
-    let mut prev_expr = None;
-    let mut index: usize = 0;
-    for field in fields.named {
-        let method_name = get_field_identifier(field.clone());
-        let field_name_upper = format_ident!("{}", method_name.to_string().to_upper_camel_case());
-        let index_expr = match (prev_expr.clone(), index) {
-            (None, 0) => None,
-            (None, y) => Some(quote!(#y)),
-            (Some(x), 0) => Some(x),
-            (Some(x), y) => Some(quote!(#x + #y)),
-        };
-        if is_skipped(field.clone()) {
-            continue;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let identifier = format_ident!("{}", name);
+    let model = quote!(<#identifier #ty_generics as fieldset::FieldSetModel>);
+    let (setter, discard) = match storage {
+        Storage::Bit => (
+            quote!(fieldset::BitFieldSetter(
+                self.bitset.offset(0),
+                &mut self.fields,
+                &mut self.len
+            )),
+            quote!(setter.discard(index, #model::superseded_fields(&value), #model::field_index);),
+        ),
+        Storage::Perf => (
+            quote!(fieldset::PerfFieldSetter(
+                &mut self.bitset,
+                &mut self.fields,
+                &mut self.len
+            )),
+            quote!(setter.discard(#model::superseded_fields(&value), #model::field_index);),
+        ),
+        Storage::Index => (
+            quote!(fieldset::IndexFieldSetter(
+                self.bitset.offset(0),
+                &mut self.fields,
+                &mut self.len
+            )),
+            quote!(setter.discard(#model::superseded_fields(&value));),
+        ),
+    };
+    quote!(
+        impl #impl_generics fieldset::FieldSink<#fieldtype_identifier #ty_generics> for #fieldset_identifier #ty_generics #where_clause {
+            fn put(&mut self, index: usize, value: #fieldtype_identifier #ty_generics) {
+                let mut setter = #setter;
+                #discard
+                fieldset::FieldSink::put(&mut setter, index, value)
+            }
+        }
+    )
+    .into()
+}
+
+fn derive_common_fieldset_into_iterator(
+    storage: Storage,
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+    derives: Vec<Path>,
+) -> TokenStream {
+    let fieldset_identifier = storage.fieldset_identifier(&name);
+    let iter_identifier = format_ident!("{}Iter", fieldset_identifier);
+    let visibility = match storage {
+        Storage::Bit => quote!(),
+        Storage::Perf | Storage::Index => quote!(pub),
+    };
+    let fieldtype_identifier = format_ident!("{}FieldType", name);
+    let fieldset_variance = get_layout_variance(generics.clone(), fields.clone());
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let derive_clone_debug = get_forwarded_derives(derives, &["Clone", "Debug"]);
+    let (iter_fields, next, into_iter) = match storage {
+        Storage::Bit | Storage::Perf => (
+            quote!(
+                fields: core::array::IntoIter<Option<#fieldtype_identifier #ty_generics>, #fieldset_variance>,
+            ),
+            quote!(
+                // Modified fields are stored contiguously from the start of the array.
+                self.fields.next().flatten()
+            ),
+            quote!(
+                fields: self.fields.into_iter(),
+            ),
+        ),
+        Storage::Index => (
+            quote!(
+                bitset: fieldset::BitSet<{#fieldset_variance.div_ceil(32)}>,
+                fields: [Option<#fieldtype_identifier #ty_generics> ; #fieldset_variance],
+                index: usize,
+            ),
+            quote!(
+                let index = self.bitset.next_set(self.index)?;
+                self.index = index + 1;
+                self.fields[index].take()
+            ),
+            quote!(
+                bitset: self.bitset,
+                fields: self.fields,
+                index: 0,
+            ),
+        ),
+    };
+    quote!(
+        #derive_clone_debug
+        #visibility struct #iter_identifier #generics #where_clause {
+            #iter_fields
+        }
+
+        impl #impl_generics Iterator for #iter_identifier #ty_generics #where_clause {
+            type Item = #fieldtype_identifier #ty_generics;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                #next
+            }
+        }
+
+        impl #impl_generics IntoIterator for #fieldset_identifier #ty_generics #where_clause {
+            type Item = #fieldtype_identifier #ty_generics;
+            type IntoIter = #iter_identifier #ty_generics;
+
+            fn into_iter(self) -> Self::IntoIter {
+                #iter_identifier {
+                    #into_iter
+                }
+            }
+        }
+    )
+    .into()
+}
+
+fn derive_bitset_fieldset(
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+    derives: Vec<Path>,
+) -> TokenStream {
+    let fieldset_identifier = format_ident!("{}BitFieldSet", name);
+    let fieldtype_identifier = format_ident!("{}FieldType", name);
+    let fieldset_variance = get_layout_variance(generics.clone(), fields.clone());
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let derive_debug = get_forwarded_derives(derives, &["Debug"]);
+    quote!(
+        #derive_debug
+        struct #fieldset_identifier #generics #where_clause {
+            bitset: fieldset::BitSet<{#fieldset_variance.div_ceil(32)}>,
+            fields: [Option<#fieldtype_identifier #ty_generics> ; #fieldset_variance],
+            len: usize,
+        }
+
+        impl #impl_generics #fieldset_identifier #ty_generics #where_clause {
+            pub const fn new() -> Self {
+                Self {
+                    bitset: fieldset::BitSet::new(),
+                    fields: [const { None }; #fieldset_variance],
+                    len: 0,
+                }
+            }
+        }
+
+        impl #impl_generics Default for #fieldset_identifier #ty_generics #where_clause {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+    )
+    .into()
+}
+
+fn derive_perf_fieldset(
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+    derives: Vec<Path>,
+    perf_index: Option<Type>,
+) -> TokenStream {
+    let fieldset_identifier = format_ident!("{}PerfFieldSet", name);
+    let fieldtype_identifier = format_ident!("{}FieldType", name);
+    let fieldset_variance = get_layout_variance(generics.clone(), fields.clone());
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let derive_debug = get_forwarded_derives(derives, &["Debug"]);
+    // The variance of nested models is only known to the compiler, so the narrowest index is
+    // selected through a trait rather than by the macro.
+    let perf_index = match perf_index {
+        Some(perf_index) => quote!(#perf_index),
+        None => {
+            quote!(<fieldset::PerfIndexBits<{fieldset::perf_index_bits(#fieldset_variance)}> as fieldset::SelectPerfIndex>::Index)
+        }
+    };
+    let message = format!("`perf_index` is too narrow for the fields of `{}`", name);
+    quote!(
+        const _: () = assert!(#fieldset_variance <= <#perf_index as fieldset::PerfIndex>::MAX, #message);
+
+        #derive_debug
+        pub struct #fieldset_identifier #generics #where_clause {
+            bitset: [#perf_index ; #fieldset_variance],
+            fields: [Option<#fieldtype_identifier #ty_generics> ; #fieldset_variance],
+            len: usize,
+        }
+
+        impl #impl_generics #fieldset_identifier #ty_generics #where_clause {
+            pub const fn new() -> Self {
+                Self {
+                    bitset: [fieldset::PerfIndex::ZERO; #fieldset_variance],
+                    fields: [const { None }; #fieldset_variance],
+                    len: 0,
+                }
+            }
+        }
+
+        impl #impl_generics Default for #fieldset_identifier #ty_generics #where_clause {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+    )
+    .into()
+}
+
+fn derive_index_fieldset(
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+    derives: Vec<Path>,
+) -> TokenStream {
+    let fieldset_identifier = format_ident!("{}IndexFieldSet", name);
+    let fieldtype_identifier = format_ident!("{}FieldType", name);
+    let fieldset_variance = get_layout_variance(generics.clone(), fields.clone());
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let derive_debug = get_forwarded_derives(derives, &["Debug"]);
+    quote!(
+        #derive_debug
+        pub struct #fieldset_identifier #generics #where_clause {
+            bitset: fieldset::BitSet<{#fieldset_variance.div_ceil(32)}>,
+            // Modifications are stored at the index of their field.
+            fields: [Option<#fieldtype_identifier #ty_generics> ; #fieldset_variance],
+            len: usize,
+        }
+
+        impl #impl_generics #fieldset_identifier #ty_generics #where_clause {
+            pub const fn new() -> Self {
+                Self {
+                    bitset: fieldset::BitSet::new(),
+                    fields: [const { None }; #fieldset_variance],
+                    len: 0,
+                }
+            }
+        }
+
+        impl #impl_generics Default for #fieldset_identifier #ty_generics #where_clause {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+    )
+    .into()
+}
+
+fn derive_field_mask(name: String, generics: Generics, fields: Vec<TrackedField>) -> TokenStream {
+    let mask_identifier = format_ident!("{}Mask", name);
+    let bitset_identifier = format_ident!("{}BitFieldSet", name);
+    let index_fieldset_identifier = format_ident!("{}IndexFieldSet", name);
+    let perf_fieldset_identifier = format_ident!("{}PerfFieldSet", name);
+    let fieldset_variance = get_layout_variance(generics.clone(), fields.clone());
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    // The mask is not generic, so the fields are positioned with the variances used for array
+    // lengths.
+    let mut masks = Vec::new();
+    let mut start = quote!(0);
+    for field in fields {
+        let mask_name = format_ident!("{}", field.method_name.unraw().to_string().to_uppercase());
+        let end = if field.is_fieldset {
+            let nested_variance = get_nested_layout_variance(field.clone(), generics.clone());
+            quote!(#start + #nested_variance)
+        } else {
+            quote!(#start + 1)
+        };
+        masks.push(quote!(
+            pub const #mask_name: Self = Self(fieldset::BitSet::new().with_range(#start, #end));
+        ));
+        start = end;
+    }
+    quote!(
+        /// Set of fields of the flattened `0..VARIANCE` layout, with a constant per field that
+        /// covers the whole nested model for `#[fieldset]` fields.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub struct #mask_identifier(fieldset::BitSet<{#fieldset_variance.div_ceil(32)}>);
+
+        impl #mask_identifier {
+            #( #masks )*
+
+            // The constants are named after the fields, so the built-in masks are functions.
+            pub const fn empty() -> Self {
+                Self(fieldset::BitSet::new())
+            }
+
+            pub const fn all() -> Self {
+                Self(fieldset::BitSet::new().with_range(0, #fieldset_variance))
+            }
+
+            pub const fn union(self, other: Self) -> Self {
+                Self(self.0.union(other.0))
+            }
+
+            pub fn intersects(&self, other: &Self) -> bool {
+                self.0.intersects(&other.0)
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.0.is_empty()
+            }
+        }
+
+        impl fieldset::FieldMask for #mask_identifier {
+            fn contains(&self, index: usize) -> bool {
+                self.0.test(index)
+            }
+        }
+
+        impl Default for #mask_identifier {
+            fn default() -> Self {
+                Self::empty()
+            }
+        }
+
+        impl core::ops::BitOr for #mask_identifier {
+            type Output = Self;
+
+            fn bitor(self, other: Self) -> Self {
+                self.union(other)
+            }
+        }
+
+        impl core::ops::BitOrAssign for #mask_identifier {
+            fn bitor_assign(&mut self, other: Self) {
+                *self = self.union(other);
+            }
+        }
+
+        impl #impl_generics #bitset_identifier #ty_generics #where_clause {
+            /// Whether any field of `mask` is modified, in a single pass over the words of the mask.
+            pub fn is_any_modified(&self, mask: &#mask_identifier) -> bool {
+                self.bitset.intersects(&mask.0)
+            }
+        }
+
+        impl #impl_generics #perf_fieldset_identifier #ty_generics #where_clause {
+            /// Whether any field of `mask` is modified, by checking the position of each field of the
+            /// mask, as the positions are not tracked in words.
+            pub fn is_any_modified(&self, mask: &#mask_identifier) -> bool {
+                let mut index = 0;
+                while let Some(field) = mask.0.next_set(index) {
+                    if fieldset::PerfIndex::to_position(self.bitset[field]) != 0 {
+                        return true;
+                    }
+                    index = field + 1;
+                }
+                false
+            }
+        }
+
+        impl #impl_generics #index_fieldset_identifier #ty_generics #where_clause {
+            /// Whether any field of `mask` is modified, in a single pass over the words of the mask.
+            pub fn is_any_modified(&self, mask: &#mask_identifier) -> bool {
+                self.bitset.intersects(&mask.0)
+            }
+        }
+    )
+    .into()
+}
+
+fn derive_opt_fieldset_methods(
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+    derives: Vec<Path>,
+) -> TokenStream {
+    let fieldset_identifier = format_ident!("{}OptFieldSet", name);
+    let iter_identifier = format_ident!("{}OptFieldSetIter", name);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    // The set does not hold `FieldType` values to borrow, so iterating by reference clones them.
+    let iter = if !derives.iter().any(|p| is_derived(p, "Clone")) {
+        quote!()
+    } else {
+        let mut field_identifiers = Vec::new();
+        let mut iter_values = Vec::new();
+        for field in fields {
+            let field_identifier = field.method_name;
+            if field.is_fieldset {
+                iter_values.push(quote!(self.#field_identifier.iter()));
+            } else {
+                iter_values.push(quote!(self.#field_identifier.clone()));
+            }
+            field_identifiers.push(field_identifier);
+        }
+        quote!(
+            pub fn iter(&self) -> #iter_identifier #ty_generics {
+                #iter_identifier {
+                    #( #field_identifiers : #iter_values ,)*
+                }
+            }
+        )
+    };
+    quote!(
+        impl #impl_generics #fieldset_identifier #ty_generics #where_clause {
+            #iter
+
+            pub fn drain(&mut self) -> #iter_identifier #ty_generics {
+                core::mem::take(self).into_iter()
+            }
+
+            pub fn clear(&mut self) {
+                *self = Self::default();
+            }
+        }
+
+        impl #impl_generics fieldset::FieldClear for #fieldset_identifier #ty_generics #where_clause {
+            fn clear(&mut self) {
+                #fieldset_identifier::clear(self)
+            }
+        }
+    )
+    .into()
+}
+
+fn derive_common_fieldset_methods(
+    storage: Storage,
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+) -> TokenStream {
+    let identifier = format_ident!("{}", name);
+    let fieldset_identifier = storage.fieldset_identifier(&name);
+    let fieldtype_identifier = format_ident!("{}FieldType", name);
+    let fieldset_variance = get_layout_variance(generics.clone(), fields.clone());
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let index = quote!(<#identifier #ty_generics as fieldset::FieldSetModel>::field_index(field));
+    let model = quote!(<#identifier #ty_generics as fieldset::FieldSetModel>);
+    let untrack = match storage {
+        Storage::Perf => quote!(self.bitset[#index] = fieldset::PerfIndex::from_position(0)),
+        Storage::Bit | Storage::Index => quote!(self.bitset.set(#index, false)),
+    };
+    // The positions of a `BitFieldSet` are not tracked, so only a `PerfFieldSet` reindexes.
+    let reindex = match storage {
+        Storage::Perf => quote!(
+            for (position, field) in self.fields[..self.len].iter().flatten().enumerate() {
+                self.bitset[#model::field_index(field)] = fieldset::PerfIndex::from_position(position + 1);
+            }
+        ),
+        Storage::Bit | Storage::Index => quote!(),
+    };
+    let methods = match storage {
+        Storage::Bit | Storage::Perf => quote!(
+            pub fn iter(&self) -> fieldset::Iter<'_, #fieldtype_identifier #ty_generics> {
+                fieldset::Iter::new(&self.fields[..self.len])
+            }
+
+            pub fn drain(&mut self) -> fieldset::Drain<'_, #fieldtype_identifier #ty_generics> {
+                // Only the slots of the pending modifications are reset.
+                for field in self.fields[..self.len].iter().flatten() {
+                    #untrack;
+                }
+                let len = core::mem::take(&mut self.len);
+                fieldset::Drain::new(&mut self.fields[..len])
+            }
+
+            /// Reorders the pending modifications into declaration order, the flattened
+            /// `0..VARIANCE` layout, instead of the order in which they were first made.
+            pub fn sort(&mut self) {
+                self.fields[..self.len].sort_unstable_by_key(|field| {
+                    field.as_ref().map_or(usize::MAX, #model::field_index)
+                });
+                #reindex
+            }
+        ),
+        // Modifications are already in declaration order, at the index of their field.
+        Storage::Index => quote!(
+            pub fn iter(&self) -> fieldset::IndexIter<'_, #fieldtype_identifier #ty_generics, {#fieldset_variance.div_ceil(32)}> {
+                fieldset::IndexIter::new(&self.bitset, &self.fields)
+            }
+
+            pub fn drain(&mut self) -> fieldset::IndexDrain<'_, #fieldtype_identifier #ty_generics, {#fieldset_variance.div_ceil(32)}> {
+                self.len = 0;
+                fieldset::IndexDrain::new(core::mem::take(&mut self.bitset), &mut self.fields)
+            }
+        ),
+    };
+    // Only the slots of the pending modifications are reset, rather than the whole arrays.
+    let clear = match storage {
+        Storage::Bit | Storage::Perf => quote!(
+            for field in self.fields[..self.len].iter().flatten() {
+                #untrack;
+            }
+            for field in &mut self.fields[..self.len] {
+                *field = None;
+            }
+            self.len = 0;
+        ),
+        Storage::Index => quote!(
+            let mut index = 0;
+            while let Some(field) = self.bitset.next_set(index) {
+                self.bitset.set(field, false);
+                self.fields[field] = None;
+                index = field + 1;
+            }
+            self.len = 0;
+        ),
+    };
+    quote!(
+        impl #impl_generics #fieldset_identifier #ty_generics #where_clause {
+            #methods
+
+            pub fn clear(&mut self) {
+                #clear
+            }
+        }
+
+        impl #impl_generics fieldset::FieldClear for #fieldset_identifier #ty_generics #where_clause {
+            fn clear(&mut self) {
+                #fieldset_identifier::clear(self)
+            }
+        }
+    )
+    .into()
+}
+
+/// Zero-sized field that uses the generic parameters of a model in an item holding none of
+/// their values.
+fn get_phantom_params(generics: Generics) -> proc_macro2::TokenStream {
+    let params = generics.params.iter().filter_map(|p| match p {
+        GenericParam::Type(t) => {
+            let ident = &t.ident;
+            Some(quote!(#ident))
+        }
+        GenericParam::Lifetime(l) => {
+            let lifetime = &l.lifetime;
+            Some(quote!(&#lifetime ()))
+        }
+        GenericParam::Const(_) => None,
+    });
+    quote!(core::marker::PhantomData<fn() -> (#( #params ,)*)>)
+}
+
+fn get_field_id_marker(generics: Generics) -> Option<proc_macro2::TokenStream> {
+    if generics.params.is_empty() {
+        return None;
+    }
+    let phantom = get_phantom_params(generics);
+    Some(quote!(
+        #[doc(hidden)]
+        __Marker(core::convert::Infallible, #phantom)
+    ))
+}
+
+fn get_field_id_marker_arm(name: String, generics: Generics) -> proc_macro2::TokenStream {
+    let field_id_identifier = format_ident!("{}FieldId", name);
+    if generics.params.is_empty() {
+        quote!()
+    } else {
+        quote!(#field_id_identifier::__Marker(never, _) => match never {},)
+    }
+}
+
+fn derive_field_id(name: String, generics: Generics, fields: Vec<TrackedField>) -> TokenStream {
+    let field_id_identifier = format_ident!("{}FieldId", name);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let marker = get_field_id_marker(generics.clone()).into_iter();
+    let marker_arm = get_field_id_marker_arm(name.clone(), generics.clone());
+    let mut variants = Vec::new();
+    let mut index_arms = Vec::new();
+    let mut debug_arms = Vec::new();
+    for field in fields {
+        let variant_name = field.variant_name;
+        let index_expr = field.index_expr;
+        let variant_string = variant_name.to_string();
+        if field.is_fieldset {
+            let ty = field.ty;
+            variants.push(quote!(#variant_name(<#ty as fieldset::FieldSetModel>::FieldId)));
+            index_arms.push(
+                quote!(Self::#variant_name(x) => #index_expr + fieldset::FieldIndex::index(x)),
+            );
+            debug_arms.push(
+                quote!(Self::#variant_name(x) => f.debug_tuple(#variant_string).field(&x).finish()),
+            );
+        } else {
+            variants.push(quote!(#variant_name));
+            index_arms.push(quote!(Self::#variant_name => #index_expr));
+            debug_arms.push(quote!(Self::#variant_name => f.write_str(#variant_string)));
+        }
+    }
+    // The traits are implemented by hand, since deriving them would bound the generic parameters
+    // of the model even though identifiers hold none of their values.
+    quote!(
+        pub enum #field_id_identifier #generics #where_clause {
+            #( #variants ,)*
+            #( #marker ,)*
+        }
+
+        impl #impl_generics #field_id_identifier #ty_generics #where_clause {
+            /// Position of the field in the flattened `0..VARIANCE` layout.
+            pub fn index(self) -> usize {
+                fieldset::FieldIndex::index(self)
+            }
+        }
+
+        impl #impl_generics fieldset::FieldIndex for #field_id_identifier #ty_generics #where_clause {
+            fn index(self) -> usize {
+                match self {
+                    #( #index_arms ,)*
+                    #marker_arm
+                }
+            }
+        }
+
+        impl #impl_generics fieldset::FieldMask for #field_id_identifier #ty_generics #where_clause {
+            fn contains(&self, index: usize) -> bool {
+                self.index() == index
+            }
+        }
+
+        impl #impl_generics Clone for #field_id_identifier #ty_generics #where_clause {
+            fn clone(&self) -> Self {
+                *self
+            }
+        }
+
+        impl #impl_generics Copy for #field_id_identifier #ty_generics #where_clause {}
+
+        impl #impl_generics PartialEq for #field_id_identifier #ty_generics #where_clause {
+            fn eq(&self, other: &Self) -> bool {
+                self.index() == other.index()
+            }
+        }
+
+        impl #impl_generics Eq for #field_id_identifier #ty_generics #where_clause {}
+
+        impl #impl_generics core::hash::Hash for #field_id_identifier #ty_generics #where_clause {
+            fn hash<__H: core::hash::Hasher>(&self, state: &mut __H) {
+                core::hash::Hash::hash(&self.index(), state)
+            }
+        }
+
+        impl #impl_generics core::fmt::Debug for #field_id_identifier #ty_generics #where_clause {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                match *self {
+                    #( #debug_arms ,)*
+                    #marker_arm
+                }
+            }
+        }
+    )
+    .into()
+}
+
+fn get_getter_generics(generics: Generics) -> Generics {
+    let mut getter_generics = generics.clone();
+    getter_generics.params.insert(0, parse_quote!('__p));
+    let where_clause = getter_generics.make_where_clause();
+    for param in generics.params {
+        match param {
+            GenericParam::Type(t) => {
+                let ident = t.ident;
+                where_clause.predicates.push(parse_quote!(#ident: '__p));
+            }
+            GenericParam::Lifetime(l) => {
+                let lifetime = l.lifetime;
+                where_clause.predicates.push(parse_quote!(#lifetime: '__p));
+            }
+            GenericParam::Const(_) => {}
+        }
+    }
+    getter_generics
+}
+
+fn derive_getter_trait(name: String, generics: Generics, fields: Vec<TrackedField>) -> TokenStream {
+    let identifier = format_ident!("{}", name);
+    let getter_trait_identifier = format_ident!("{}FieldGetter", name);
+    let fieldtype_identifier = format_ident!("{}FieldType", name);
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let model = quote!(#identifier #ty_generics);
+    let getter_generics = get_getter_generics(generics.clone());
+    let (_, getter_ty_generics, getter_where_clause) = getter_generics.split_for_impl();
+    let mut blanket_generics = getter_generics.clone();
+    blanket_generics
+        .params
+        .push(parse_quote!(__G: fieldset::PendingView<'__p, #model> + ?Sized));
+    let (blanket_impl_generics, _, _) = blanket_generics.split_for_impl();
+    let mut methods = Vec::new();
+    for field in fields {
+        let method_name = field.method_name;
+        let variant_name = field.variant_name;
+        let index_expr = field.index_expr;
+        let ty = field.ty;
+        let unwrap = get_unwrap(quote!(#fieldtype_identifier), &variant_name);
+        if field.is_fieldset {
+            methods.push(quote!(
+                fn #method_name(&self) -> <Self as fieldset::PendingView<'__p, #model>>::Nested<#ty> {
+                    fieldset::PendingView::nested(self, #index_expr, |x| &x.#method_name, #unwrap)
+                }
+            ));
+        } else {
+            methods.push(quote!(
+                fn #method_name(&self) -> Option<&'__p #ty> {
+                    fieldset::PendingView::get(self, #index_expr, |x| x.#method_name.as_ref(), #unwrap)
+                }
+            ));
+        }
+    }
+    quote!(
+        pub trait #getter_trait_identifier #getter_generics : fieldset::PendingView<'__p, #model> #getter_where_clause {
+            #( #methods )*
+        }
+
+        impl #blanket_impl_generics #getter_trait_identifier #getter_ty_generics for __G #getter_where_clause {}
+    )
+    .into()
+}
+
+fn derive_opt_fieldset_queries(
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+) -> TokenStream {
+    let identifier = format_ident!("{}", name);
+    let fieldset_identifier = format_ident!("{}OptFieldSet", name);
+    let field_id_identifier = format_ident!("{}FieldId", name);
+    let marker_arm = get_field_id_marker_arm(name.clone(), generics.clone());
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let mut lens = Vec::new();
+    let mut is_modified_arms = Vec::new();
+    for field in fields {
+        let field_identifier = field.method_name;
+        let variant_name = field.variant_name;
+        if field.is_fieldset {
+            lens.push(quote!(self.#field_identifier.len()));
+            is_modified_arms.push(quote!(#field_id_identifier::#variant_name(x) => self.#field_identifier.is_modified(x)));
+        } else {
+            lens.push(quote!(usize::from(self.#field_identifier.is_some())));
+            is_modified_arms.push(
+                quote!(#field_id_identifier::#variant_name => self.#field_identifier.is_some()),
+            );
+        }
+    }
+    quote!(
+        impl #impl_generics #fieldset_identifier #ty_generics #where_clause {
+            pub fn len(&self) -> usize {
+                0 #( + #lens )*
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+
+            pub fn is_modified(&self, id: #field_id_identifier #ty_generics) -> bool {
+                match id {
+                    #( #is_modified_arms ,)*
+                    #marker_arm
+                }
+            }
+
+            pub fn pending(&self) -> fieldset::OptPending<'_, #identifier #ty_generics> {
+                fieldset::OptPending::new(self)
+            }
+        }
+    )
+    .into()
+}
+
+fn derive_common_fieldset_queries(
+    storage: Storage,
+    name: String,
+    generics: Generics,
+    _fields: Vec<TrackedField>,
+) -> TokenStream {
+    let identifier = format_ident!("{}", name);
+    let fieldset_identifier = storage.fieldset_identifier(&name);
+    let fieldtype_identifier = format_ident!("{}FieldType", name);
+    let field_id_identifier = format_ident!("{}FieldId", name);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let (is_modified, lookup) = match storage {
+        Storage::Bit => (
+            quote!(self.bitset.test(id.index())),
+            // Positions are not tracked, so the pending modification is searched for.
+            quote!(
+                if !self.bitset.test(index) {
+                    return None;
+                }
+                self.fields[..self.len].iter().flatten().find(|field| {
+                    <#identifier #ty_generics as fieldset::FieldSetModel>::field_index(field) == index
+                })
+            ),
+        ),
+        Storage::Perf => (
+            quote!(fieldset::PerfIndex::to_position(self.bitset[id.index()]) != 0),
+            quote!(match fieldset::PerfIndex::to_position(self.bitset[index]) {
+                0 => None,
+                position => self.fields[position - 1].as_ref(),
+            }),
+        ),
+        Storage::Index => (
+            quote!(self.bitset.test(id.index())),
+            quote!(self.fields[index].as_ref()),
+        ),
+    };
+    quote!(
+        impl #impl_generics #fieldset_identifier #ty_generics #where_clause {
+            pub fn len(&self) -> usize {
+                self.len
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.len == 0
+            }
+
+            pub fn is_modified(&self, id: #field_id_identifier #ty_generics) -> bool {
+                #is_modified
+            }
+
+            pub fn pending(&self) -> fieldset::Pending<&Self> {
+                fieldset::Pending::new(self)
+            }
+        }
+
+        impl #impl_generics fieldset::FieldSource<#fieldtype_identifier #ty_generics> for #fieldset_identifier #ty_generics #where_clause {
+            fn lookup(&self, index: usize) -> Option<&#fieldtype_identifier #ty_generics> {
+                #lookup
+            }
+        }
+    )
+    .into()
+}
+
+/// Metadata of the nested model of `field` for the field table, whose length is that of the paths
+/// of all fields, so like variances it is reached through the layout type.
+fn get_nested_fields(field: TrackedField, generics: Generics) -> proc_macro2::TokenStream {
+    let ty = get_layout_type(field, generics);
+    quote!(<#ty as fieldset::FieldSetModel>::FIELDS)
+}
+
+fn derive_field_table(name: String, generics: Generics, fields: Vec<TrackedField>) -> TokenStream {
+    let identifier = format_ident!("{}", name);
+    let field_id_identifier = format_ident!("{}FieldId", name);
+    let fieldtype_identifier = format_ident!("{}FieldType", name);
+    let variance_identifier = get_layout_variance(generics.clone(), fields.clone());
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let mut leaves_len: usize = 0;
+    let mut nested_lens = Vec::new();
+    let mut table_entries = Vec::new();
+    let mut id_arms = Vec::new();
+    for field in fields {
+        let method_name = field.method_name.unraw().to_string();
+        let variant_name = field.variant_name.clone();
+        if field.is_fieldset {
+            let nested_fields = get_nested_fields(field, generics.clone());
+            nested_lens.push(quote!(fieldset::nested_paths_len(#method_name, #nested_fields)));
+            table_entries.push(quote!(.nested(#method_name, #nested_fields)));
+            id_arms.push(
+                quote!(Self::#variant_name(x) => #field_id_identifier::#variant_name(x.id())),
+            );
+        } else {
+            leaves_len += method_name.len();
+            table_entries.push(quote!(.leaf(#method_name)));
+            id_arms.push(quote!(Self::#variant_name(_) => #field_id_identifier::#variant_name));
+        }
+    }
+    let id_arms = match_borrowed(quote!(self), id_arms);
+    quote!(
+        impl #impl_generics #identifier #ty_generics #where_clause {
+            /// Metadata of the tracked fields, in the flattened `0..VARIANCE` layout.
+            pub const FIELDS: [fieldset::FieldInfo; #variance_identifier] = {
+                // The table only depends on erased types, so it is built in items of its own
+                // whose paths outlive the constant.
+                const PATHS_LEN: usize = #leaves_len #( + #nested_lens )*;
+                const TABLE: fieldset::FieldTable<#variance_identifier, PATHS_LEN> =
+                    fieldset::FieldTable::new() #( #table_entries )*;
+                const PATHS: [u8; PATHS_LEN] = TABLE.paths;
+                TABLE.fields(&PATHS)
+            };
+        }
+
+        impl #impl_generics #field_id_identifier #ty_generics #where_clause {
+            pub fn name(self) -> &'static str {
+                <#identifier #ty_generics as fieldset::FieldSetModel>::FIELDS[self.index()].name
+            }
+
+            /// Dotted path of the field from this model.
+            pub fn path(self) -> &'static str {
+                <#identifier #ty_generics as fieldset::FieldSetModel>::FIELDS[self.index()].path
+            }
+        }
+
+        impl #impl_generics #fieldtype_identifier #ty_generics #where_clause {
+            pub fn id(&self) -> #field_id_identifier #ty_generics {
+                #id_arms
+            }
+
+            /// Position of the modified field in the flattened `0..VARIANCE` layout.
+            pub fn index(&self) -> usize {
+                self.id().index()
+            }
+
+            pub fn name(&self) -> &'static str {
+                self.id().name()
+            }
+
+            /// Dotted path of the modified field from this model.
+            pub fn path(&self) -> &'static str {
+                self.id().path()
+            }
         }
-        if is_fieldset(field.clone()) {
-            let type_identifier = get_type_identifier(field.ty);
-            let start_expr = match index_expr.clone() {
-                Some(x) => quote!(#x),
-                None => quote!(0),
-            };
-            let variance_identifier = get_variance_identifier(type_identifier.clone());
-            let end_expr = match index_expr.clone() {
-                Some(x) => quote!(#x + #variance_identifier),
-                None => quote!(#variance_identifier),
-            };
-            prev_expr = Some(end_expr.clone());
-            index = 0;
-            let field_setter_trait_identifier = format_ident!("{}FieldSetter", type_identifier);
-            let setter_name = if is_bitset {
-                format_ident!("BitFieldSetter")
-            } else {
-                format_ident!("PerfFieldSetter")
-            };
-            let bitset_param = if is_bitset {
-                quote!(#bitset_expr.offset(#start_expr))
-            } else {
-                quote!(&mut #bitset_expr[#start_expr..#end_expr])
-            };
-            res.push(quote!(
-                fn #method_name(&mut self) -> impl #field_setter_trait_identifier {
-                    let f = #fun_expr;
-                    fieldset::#setter_name(
-                    #bitset_param,
-                    &mut #fields_expr,
-                    &mut #len_expr,
-                    move |x|
-                            f(#fieldtype_identifier::#field_name_upper(x)))
-                }
-            ));
+    )
+    .into()
+}
+
+fn derive_field_paths(name: String, generics: Generics, fields: Vec<TrackedField>) -> TokenStream {
+    let path_identifier = format_ident!("{}Path", name);
+    let fieldtype_identifier = format_ident!("{}FieldType", name);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let mut paths = Vec::new();
+    for field in fields {
+        let const_identifier =
+            format_ident!("{}", field.method_name.unraw().to_string().to_uppercase());
+        let variant_name = field.variant_name;
+        let index_expr = field.index_expr;
+        let ty = field.ty;
+        let unwrap = get_unwrap(quote!(#fieldtype_identifier), &variant_name);
+        let (value, end) = if field.is_fieldset {
+            let nested_variance = get_nested_variance(ty.clone());
+            (
+                quote!(<#ty as fieldset::FieldSetModel>::FieldType),
+                quote!(#index_expr + #nested_variance),
+            )
         } else {
-            let ty = field.ty;
-            let index_expr = index_expr.or_else(|| quote!(0usize).into());
-            index += 1;
-            let leaf_setter_name = if is_bitset {
-                format_ident!("BitFieldLeafSetter")
-            } else {
-                format_ident!("PerfFieldLeafSetter")
-            };
-            let bitset_param = if is_bitset {
-                quote!(#bitset_expr.offset(0))
-            } else {
-                quote!(&mut #bitset_expr)
-            };
-            res.push(quote!(
-                fn #method_name(&mut self) -> impl fieldset::FieldSetter<#ty> {
-                    let f = #fun_expr;
-                    fieldset::#leaf_setter_name::<#ty, _, _>(
-                        #bitset_param,
-                        &mut #fields_expr,
-                        &mut #len_expr,
-                        #index_expr, move |x| f(#fieldtype_identifier::#field_name_upper(x)), core::marker::PhantomData)
-                }
-            ));
+            (quote!(#ty), quote!(#index_expr + 1))
+        };
+        paths.push(quote!(
+            pub const #const_identifier: fieldset::FieldPath<#fieldtype_identifier #ty_generics, #value> =
+                fieldset::FieldPath::new(#index_expr..#end, #unwrap);
+        ));
+    }
+    // The constants live on their own type, as those of the `FieldType` would share the
+    // namespace of its variants.
+    let path_type = if generics.params.is_empty() {
+        quote!(pub struct #path_identifier;)
+    } else {
+        let phantom = get_phantom_params(generics.clone());
+        quote!(pub struct #path_identifier #generics (#phantom) #where_clause;)
+    };
+    quote!(
+        /// `FieldPath` constants of the fields of the model, to select them in a `Dispatcher`.
+        #path_type
+
+        impl #impl_generics #path_identifier #ty_generics #where_clause {
+            #( #paths )*
         }
+    )
+    .into()
+}
+
+fn get_field_diff(
+    field: TrackedField,
+    old: proc_macro2::TokenStream,
+    new: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let method_name = field.method_name;
+    let ty = field.ty;
+    if field.is_fieldset {
+        quote!(<#ty as fieldset::FieldSetDiff>::diff(#old, #new, &mut setter.#method_name());)
+    } else {
+        quote!(
+            if #old != #new {
+                fieldset::FieldSetter::set(&mut setter.#method_name(), Clone::clone(#new));
+            }
+        )
     }
-    quote!(#(#res )*)
 }
 
-fn derive_common_fieldset_setter_trait_impl(
-    is_bitset: bool,
+fn derive_fieldset_diff(
     name: String,
-    fields: FieldsNamed,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+    derives: Vec<Path>,
 ) -> TokenStream {
-    let bitset_expr = quote!(self.0);
-    let fields_expr = quote!(self.1);
-    let len_expr = quote!(self.2);
-    let fun_expr = quote!(self.3);
-    let trait_identifier = format_ident!("{}FieldSetter", name);
+    // Deriving `Clone` and `PartialEq` for the `FieldType` requires them of every tracked field.
+    if !["Clone", "PartialEq"]
+        .iter()
+        .all(|name| derives.iter().any(|p| is_derived(p, name)))
+    {
+        return TokenStream::default();
+    }
+    let identifier = format_ident!("{}", name);
+    let setter_trait_identifier = format_ident!("{}FieldSetter", name);
     let fieldtype_identifier = format_ident!("{}FieldType", name);
-    let methods = common_trait_impl_methods(
-        bitset_expr,
-        fields_expr,
-        len_expr,
-        fun_expr,
-        is_bitset,
-        name,
-        fields,
-    );
-    let setters_name = if is_bitset {
-        format_ident!("BitFieldSetter")
-    } else {
-        format_ident!("PerfFieldSetter")
-    };
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let mut diff_generics = generics.clone();
+    let where_clause = diff_generics.make_where_clause();
+    let mut diffs = Vec::new();
+    let mut variant_diffs: Vec<(Ident, Vec<_>, Vec<_>, Vec<_>)> = Vec::new();
+    let mut is_enum = false;
+    for field in fields {
+        let ty = field.ty.clone();
+        if field.is_fieldset {
+            where_clause
+                .predicates
+                .push(parse_quote!(#ty: fieldset::FieldSetDiff));
+        } else if depends_on_generics(ty.clone(), generics.clone()) {
+            where_clause
+                .predicates
+                .push(parse_quote!(#ty: PartialEq + Clone));
+        }
+        match field.access.clone() {
+            FieldAccess::Member(member) => {
+                diffs.push(get_field_diff(
+                    field,
+                    quote!(&old.#member),
+                    quote!(&new.#member),
+                ));
+            }
+            FieldAccess::VariantMember(variant, member) => {
+                let position = match variant_diffs.iter().position(|(v, ..)| *v == variant) {
+                    Some(position) => position,
+                    None => {
+                        variant_diffs.push((variant, Vec::new(), Vec::new(), Vec::new()));
+                        variant_diffs.len() - 1
+                    }
+                };
+                let (_, old_bindings, new_bindings, diffs) = &mut variant_diffs[position];
+                let old_binding = format_ident!("__old_{}", diffs.len());
+                let new_binding = format_ident!("__new_{}", diffs.len());
+                old_bindings.push(quote!(#member: #old_binding));
+                new_bindings.push(quote!(#member: #new_binding));
+                diffs.push(get_field_diff(
+                    field,
+                    quote!(#old_binding),
+                    quote!(#new_binding),
+                ));
+            }
+            FieldAccess::Switch => is_enum = true,
+        }
+    }
+    if is_enum {
+        let arms = variant_diffs.into_iter().map(|(variant, old_bindings, new_bindings, diffs)| {
+            quote!(
+                (Self::#variant { #( #old_bindings ,)* .. }, Self::#variant { #( #new_bindings ,)* .. }) => {
+                    #( #diffs )*
+                }
+            )
+        });
+        // Variants without tracked fields have no arm, so they are compared by discriminant.
+        diffs.push(quote!(
+            match (old, new) {
+                #( #arms )*
+                _ if core::mem::discriminant(old) != core::mem::discriminant(new) => {
+                    fieldset::FieldSetter::set(&mut setter.variant(), Clone::clone(new));
+                }
+                _ => {}
+            }
+        ));
+    }
+    let (impl_generics, _, where_clause) = diff_generics.split_for_impl();
     quote!(
-        impl<'a, T, F: Fn(#fieldtype_identifier) -> T + Copy> #trait_identifier for fieldset::#setters_name<'a, T, F> {
-            #methods
+        impl #impl_generics #identifier #ty_generics #where_clause {
+            /// Puts into `setter` the modifications that turn `old` into `new`, recursing into
+            /// nested models.
+            pub fn diff<__S: #setter_trait_identifier #ty_generics + ?Sized>(old: &Self, new: &Self, setter: &mut __S) {
+                #( #diffs )*
+            }
+        }
+
+        impl #impl_generics fieldset::FieldSetDiff for #identifier #ty_generics #where_clause {
+            fn diff<__S: fieldset::FieldSink<#fieldtype_identifier #ty_generics> + ?Sized>(old: &Self, new: &Self, setter: &mut __S) {
+                Self::diff(old, new, setter)
+            }
         }
-    ).into()
+    )
+    .into()
 }
 
-fn derive_common_fieldset_trait_impl(
-    is_bitset: bool,
+fn derive_fieldset_dedup(
     name: String,
-    fields: FieldsNamed,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+    derives: Vec<Path>,
 ) -> TokenStream {
-    let bitset_expr = quote!(self.bitset);
-    let fields_expr = quote!(self.fields);
-    let len_expr = quote!(self.len);
-    let fun_expr = quote!(Some);
-    let trait_identifier = format_ident!("{}FieldSetter", name);
-    let fieldset_identifier = if is_bitset {
-        format_ident!("{}BitFieldSet", name)
-    } else {
-        format_ident!("{}PerfFieldSet", name)
-    };
-    let methods = common_trait_impl_methods(
-        bitset_expr,
-        fields_expr,
-        len_expr,
-        fun_expr,
-        is_bitset,
-        name,
-        fields,
-    );
+    // Models opt in by deriving `PartialEq` for the `FieldType` or by comparing a field with a
+    // function or within a tolerance.
+    if !derives.iter().any(|p| is_derived(p, "PartialEq"))
+        && !fields.iter().any(|f| f.eq.is_some() || f.epsilon.is_some())
+    {
+        return TokenStream::default();
+    }
+    let identifier = format_ident!("{}", name);
+    let fieldtype_identifier = format_ident!("{}FieldType", name);
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let mut dedup_generics = generics.clone();
+    let where_clause = dedup_generics.make_where_clause();
+    let switch_index = fields
+        .iter()
+        .find(|field| matches!(field.access, FieldAccess::Switch))
+        .map(|field| field.index_expr.clone());
+    let mut match_arms = Vec::new();
+    for field in fields {
+        let variant_name = field.variant_name;
+        let index_expr = field.index_expr;
+        let ty = field.ty;
+        let is_unchanged = if field.is_fieldset {
+            where_clause
+                .predicates
+                .push(parse_quote_spanned!(ty.span()=> #ty: fieldset::FieldSetDedup));
+            quote!(fieldset::FieldSetDedup::is_unchanged(current, x, &|index| is_pending(#index_expr + index)))
+        } else if let Some(eq) = field.eq {
+            quote!(#eq(current, x))
+        } else if let Some(epsilon) = field.epsilon {
+            quote!({
+                let difference = *current - *x;
+                difference <= #epsilon && -difference <= #epsilon
+            })
+        } else {
+            // Fields of other types need `#[fieldset(eq = path)]` or `#[fieldset(epsilon = ...)]`,
+            // so the bound is spanned at the field to report it there.
+            where_clause
+                .predicates
+                .push(parse_quote_spanned!(ty.span()=> #ty: PartialEq));
+            quote!(current == x)
+        };
+        match field.access {
+            FieldAccess::Member(member) => {
+                match_arms.push(quote!(#fieldtype_identifier::#variant_name(x) => {
+                    let current = &self.#member;
+                    #is_unchanged
+                }));
+            }
+            // A pending variant switch replaces the current variant, so the modification is kept
+            // for the new one. Otherwise a modification of an inactive variant would be dropped
+            // when applied, so it is skipped like an unchanged value.
+            FieldAccess::VariantMember(variant, member) => {
+                match_arms.push(quote!(#fieldtype_identifier::#variant_name(x) => match self {
+                    _ if is_pending(#switch_index) => false,
+                    Self::#variant { #member: current, .. } => #is_unchanged,
+                    #[allow(unreachable_patterns)]
+                    _ => true,
+                }));
+            }
+            FieldAccess::Switch => {
+                match_arms.push(quote!(#fieldtype_identifier::#variant_name(x) => {
+                    let current = self;
+                    #is_unchanged
+                }));
+            }
+        }
+    }
+    let (impl_generics, _, where_clause) = dedup_generics.split_for_impl();
+    let match_arms = match_borrowed(quote!(field), match_arms);
     quote!(
-        impl #trait_identifier for #fieldset_identifier {
-            #methods
+        impl #impl_generics fieldset::FieldSetDedup for #identifier #ty_generics #where_clause {
+            fn is_unchanged(&self, field: &#fieldtype_identifier #ty_generics, is_pending: &dyn Fn(usize) -> bool) -> bool {
+                let _ = is_pending;
+                #match_arms
+            }
         }
+    )
+    .into()
+}
 
-        impl #trait_identifier for &mut #fieldset_identifier {
-            #methods
+fn derive_fieldset_read(
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+    derives: Vec<Path>,
+) -> TokenStream {
+    // Deriving `Clone` for the `FieldType` requires it of every tracked field.
+    if !derives.iter().any(|p| is_derived(p, "Clone")) {
+        return TokenStream::default();
+    }
+    let identifier = format_ident!("{}", name);
+    let fieldtype_identifier = format_ident!("{}FieldType", name);
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let mut read_generics = generics.clone();
+    let where_clause = read_generics.make_where_clause();
+    let switch_index = fields
+        .iter()
+        .find(|field| matches!(field.access, FieldAccess::Switch))
+        .map(|field| field.index_expr.clone());
+    let mut branches = Vec::new();
+    for field in fields {
+        let variant_name = field.variant_name;
+        let index_expr = field.index_expr;
+        let ty = field.ty;
+        let (is_read, read) = if field.is_fieldset {
+            where_clause
+                .predicates
+                .push(parse_quote!(#ty: fieldset::FieldSetRead));
+            let nested_variance = get_nested_variance(ty);
+            let unwrap = get_unwrap(quote!(#fieldtype_identifier), &variant_name);
+            (
+                quote!((#index_expr..#index_expr + #nested_variance).contains(&index)),
+                quote!(fieldset::FieldSetRead::read(current, index - (#index_expr), &|index| {
+                    pending(#index_expr + index).and_then(#unwrap)
+                })
+                .map(#fieldtype_identifier::#variant_name)),
+            )
+        } else {
+            if depends_on_generics(ty.clone(), generics.clone()) {
+                where_clause.predicates.push(parse_quote!(#ty: Clone));
+            }
+            (
+                quote!(index == #index_expr),
+                quote!(Some(#fieldtype_identifier::#variant_name(Clone::clone(current)))),
+            )
+        };
+        match field.access {
+            FieldAccess::Member(member) => {
+                branches.push(quote!(
+                    if #is_read {
+                        let current = &self.#member;
+                        return #read;
+                    }
+                ));
+            }
+            // The fields of an enum are read from the variant it is pending a switch to, if any.
+            FieldAccess::VariantMember(variant, member) => {
+                branches.push(quote!(
+                    if #is_read {
+                        if let Some(#fieldtype_identifier::Variant(switched)) = pending(#switch_index) {
+                            return fieldset::FieldSetRead::read(&switched, index, &|index| match index {
+                                #switch_index => None,
+                                index => pending(index),
+                            });
+                        }
+                        return match self {
+                            Self::#variant { #member: current, .. } => #read,
+                            _ => None,
+                        };
+                    }
+                ));
+            }
+            FieldAccess::Switch => {
+                branches.push(quote!(
+                    if #is_read {
+                        let current = self;
+                        return #read;
+                    }
+                ));
+            }
+        }
+    }
+    let (impl_generics, _, where_clause) = read_generics.split_for_impl();
+    quote!(
+        impl #impl_generics fieldset::FieldSetRead for #identifier #ty_generics #where_clause {
+            fn read(&self, index: usize, pending: &dyn Fn(usize) -> Option<#fieldtype_identifier #ty_generics>) -> Option<#fieldtype_identifier #ty_generics> {
+                if let Some(field) = pending(index) {
+                    return Some(field);
+                }
+                #( #branches )*
+                None
+            }
         }
     )
     .into()
 }
 
-fn derive_common_fieldset_into_iterator(
-    is_bitset: bool,
+fn derive_fieldset_undo(
     name: String,
-    _fields: FieldsNamed,
+    generics: Generics,
+    fields: Vec<TrackedField>,
 ) -> TokenStream {
-    let fieldset_identifier = if is_bitset {
-        format_ident!("{}BitFieldSet", name)
+    let identifier = format_ident!("{}", name);
+    let fieldtype_identifier = format_ident!("{}FieldType", name);
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let mut undo_generics = generics.clone();
+    let where_clause = undo_generics.make_where_clause();
+    let mut match_arms = Vec::new();
+    for field in fields {
+        let variant_name = field.variant_name;
+        let ty = field.ty;
+        let replacement = if field.is_fieldset {
+            where_clause
+                .predicates
+                .push(parse_quote!(#ty: fieldset::FieldSetUndo));
+            quote!(fieldset::FieldSetUndo::apply_with_undo(target, x).map(#fieldtype_identifier::#variant_name))
+        } else {
+            quote!(Some(#fieldtype_identifier::#variant_name(core::mem::replace(target, x))))
+        };
+        match field.access {
+            FieldAccess::Member(member) => {
+                match_arms.push(quote!(#fieldtype_identifier::#variant_name(x) => {
+                    let target = &mut self.#member;
+                    #replacement
+                }));
+            }
+            FieldAccess::VariantMember(variant, member) => {
+                match_arms.push(
+                    quote!(#fieldtype_identifier::#variant_name(x) => match self {
+                        Self::#variant { #member: target, .. } => #replacement,
+                        _ => None,
+                    }),
+                );
+            }
+            FieldAccess::Switch => {
+                match_arms.push(quote!(#fieldtype_identifier::#variant_name(x) => {
+                    let target = self;
+                    #replacement
+                }));
+            }
+        }
+    }
+    let (impl_generics, _, where_clause) = undo_generics.split_for_impl();
+    quote!(
+        impl #impl_generics fieldset::FieldSetUndo for #identifier #ty_generics #where_clause {
+            fn apply_with_undo(&mut self, field: #fieldtype_identifier #ty_generics) -> Option<#fieldtype_identifier #ty_generics> {
+                match field {
+                    #( #match_arms ,)*
+                }
+            }
+        }
+    )
+    .into()
+}
+
+fn derive_fieldset_validate(
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+    validate: Option<Path>,
+    derives: Vec<Path>,
+) -> TokenStream {
+    let identifier = format_ident!("{}", name);
+    let fieldtype_identifier = format_ident!("{}FieldType", name);
+    let bitset_identifier = format_ident!("{}BitFieldSet", name);
+    let field_id_identifier = format_ident!("{}FieldId", name);
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let mut validate_generics = generics.clone();
+    let where_clause = validate_generics.make_where_clause();
+    let mut field_arms = Vec::new();
+    let mut nested_checks = Vec::new();
+    for field in fields {
+        let variant_name = field.variant_name;
+        let ty = field.ty;
+        if field.is_fieldset {
+            where_clause
+                .predicates
+                .push(parse_quote!(#ty: fieldset::FieldSetValidate));
+            field_arms.push(quote!(#fieldtype_identifier::#variant_name(x) => {
+                <#ty as fieldset::FieldSetValidate>::validate_field(x).map_err(#field_id_identifier::#variant_name)
+            }));
+            let check = quote!(fieldset::FieldSetValidate::validate(x).map_err(#field_id_identifier::#variant_name)?);
+            match field.access {
+                FieldAccess::Member(member) => {
+                    nested_checks.push(quote!({
+                        let x = &self.#member;
+                        #check;
+                    }));
+                }
+                // Only the nested models of the active variant are part of the model.
+                FieldAccess::VariantMember(variant, member) => {
+                    nested_checks.push(quote!(if let Self::#variant { #member: x, .. } = self {
+                        #check;
+                    }));
+                }
+                FieldAccess::Switch => unreachable!(),
+            }
+        } else if let Some(validate) = field.validate {
+            field_arms.push(quote!(#fieldtype_identifier::#variant_name(x) => {
+                if #validate(x) {
+                    Ok(())
+                } else {
+                    Err(#field_id_identifier::#variant_name)
+                }
+            }));
+        } else {
+            field_arms.push(quote!(#fieldtype_identifier::#variant_name(_) => Ok(())));
+        }
+    }
+    let validate = match validate {
+        Some(validate) => quote!(#validate(self)),
+        None => quote!(Ok(())),
+    };
+    let (impl_generics, _, where_clause) = validate_generics.split_for_impl();
+    // The fields are cloned into a backup set to leave the model untouched on failure.
+    let try_apply_all = if !derives.iter().any(|p| is_derived(p, "Clone")) {
+        quote!()
     } else {
-        format_ident!("{}PerfFieldSet", name)
+        quote!(
+            impl #impl_generics #identifier #ty_generics #where_clause {
+                /// Applies all `fields` if each of them and the resulting model are valid, and otherwise
+                /// leaves the model untouched and returns the field that failed validation.
+                pub fn try_apply_all(
+                    &mut self,
+                    fields: impl IntoIterator<Item = #fieldtype_identifier #ty_generics>,
+                ) -> Result<(), #field_id_identifier #ty_generics>
+                where
+                    #fieldtype_identifier #ty_generics: Clone,
+                {
+                    fieldset::FieldSetValidate::try_apply_all_with(self, fields, #bitset_identifier::default())
+                }
+            }
+        )
     };
+    let field_arms = match_borrowed(quote!(field), field_arms);
+    quote!(
+        impl #impl_generics fieldset::FieldSetValidate for #identifier #ty_generics #where_clause {
+            fn validate_field(field: &#fieldtype_identifier #ty_generics) -> Result<(), #field_id_identifier #ty_generics> {
+                #field_arms
+            }
+
+            fn validate(&self) -> Result<(), #field_id_identifier #ty_generics> {
+                #( #nested_checks )*
+                #validate
+            }
+        }
+
+        #try_apply_all
+    )
+    .into()
+}
+
+fn derive_field_type_codec(
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+) -> TokenStream {
     let fieldtype_identifier = format_ident!("{}FieldType", name);
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let mut codec_generics = generics.clone();
+    let where_clause = codec_generics.make_where_clause();
+    let mut encode_arms = Vec::new();
+    let mut decode_branches = Vec::new();
+    for field in fields {
+        let variant_name = field.variant_name;
+        let index_expr = field.index_expr;
+        let ty = field.ty;
+        if field.is_fieldset {
+            let nested_fieldtype = quote!(<#ty as fieldset::FieldSetModel>::FieldType);
+            let nested_variance = get_nested_variance(ty);
+            where_clause
+                .predicates
+                .push(parse_quote!(#nested_fieldtype: fieldset::FieldTypeCodec));
+            encode_arms.push(
+                quote!(Self::#variant_name(x) => fieldset::FieldTypeCodec::encode_value(x, buf)),
+            );
+            decode_branches.push(quote!(
+                if (#index_expr..#index_expr + #nested_variance).contains(&index) {
+                    let (x, len) = <#nested_fieldtype as fieldset::FieldTypeCodec>::decode_value(index - (#index_expr), buf)
+                        .map_err(|e| match e {
+                            fieldset::CodecError::InvalidTag(_) => fieldset::CodecError::InvalidTag(index),
+                            e => e,
+                        })?;
+                    return Ok((Self::#variant_name(x), len));
+                }
+            ));
+        } else {
+            where_clause
+                .predicates
+                .push(parse_quote!(#ty: fieldset::FieldCodec));
+            encode_arms.push(quote!(Self::#variant_name(x) => fieldset::encode_value(x, buf)));
+            decode_branches.push(quote!(
+                if index == #index_expr {
+                    let (x, len) = fieldset::decode_value(buf)?;
+                    return Ok((Self::#variant_name(x), len));
+                }
+            ));
+        }
+    }
+    let (impl_generics, _, where_clause) = codec_generics.split_for_impl();
+    let encode_arms = match_borrowed(quote!(self), encode_arms);
     quote!(
-        impl IntoIterator for #fieldset_identifier {
-            type Item = #fieldtype_identifier;
-            type IntoIter = impl Iterator<Item = Self::Item> + Clone + core::fmt::Debug;
+        impl #impl_generics fieldset::FieldTypeCodec for #fieldtype_identifier #ty_generics #where_clause {
+            fn encode_value(&self, buf: &mut [u8]) -> Result<usize, fieldset::CodecError> {
+                #encode_arms
+            }
 
-            fn into_iter(self) -> Self::IntoIter {
-                self.fields.into_iter().map_while(|x| x)
+            fn decode_value(index: usize, buf: &[u8]) -> Result<(Self, usize), fieldset::CodecError> {
+                #( #decode_branches )*
+                Err(fieldset::CodecError::InvalidTag(index))
+            }
+        }
+
+        impl #impl_generics #fieldtype_identifier #ty_generics #where_clause {
+            /// Encodes the modification as the flat index of the field in a varint tag, followed
+            /// by the little-endian value, and returns the number of bytes written.
+            pub fn encode(&self, buf: &mut [u8]) -> Result<usize, fieldset::CodecError> {
+                let len = fieldset::encode_tag(self.index(), buf)?;
+                Ok(len + fieldset::FieldTypeCodec::encode_value(self, &mut buf[len..])?)
+            }
+
+            /// Decodes a modification written by `encode` and returns it along with the number of
+            /// bytes read.
+            pub fn decode(buf: &[u8]) -> Result<(Self, usize), fieldset::CodecError> {
+                let (index, len) = fieldset::decode_tag(buf)?;
+                let (value, value_len) =
+                    <Self as fieldset::FieldTypeCodec>::decode_value(index, &buf[len..])?;
+                Ok((value, len + value_len))
             }
         }
     )
     .into()
 }
 
-fn derive_bitset_fieldset(name: String, _fields: FieldsNamed) -> TokenStream {
-    let identifier = format_ident!("{}", name);
-    let fieldset_identifier = format_ident!("{}BitFieldSet", name);
+#[cfg(feature = "serde")]
+fn get_serde_generics(
+    generics: Generics,
+    lifetime: Option<syn::Lifetime>,
+    bounds: Vec<proc_macro2::TokenStream>,
+) -> Generics {
+    let mut serde_generics = generics;
+    if let Some(lifetime) = lifetime {
+        serde_generics.params.insert(0, parse_quote!(#lifetime));
+    }
+    let where_clause = serde_generics.make_where_clause();
+    for bound in bounds {
+        where_clause.predicates.push(parse_quote!(#bound));
+    }
+    serde_generics
+}
+
+#[cfg(feature = "serde")]
+fn derive_field_type_serde(
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+) -> TokenStream {
     let fieldtype_identifier = format_ident!("{}FieldType", name);
-    let fieldset_variance = get_variance_identifier(identifier);
+    let fieldtype_name = fieldtype_identifier.to_string();
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let mut serialize_bounds = Vec::new();
+    let mut deserialize_bounds = Vec::new();
+    let mut fields_bounds = Vec::new();
+    let mut names = Vec::new();
+    let mut serialize_arms = Vec::new();
+    let mut deserialize_arms = Vec::new();
+    let mut lens = Vec::new();
+    let mut entries = Vec::new();
+    for (position, field) in fields.into_iter().enumerate() {
+        let field_name = field.method_name.unraw().to_string();
+        let variant_name = field.variant_name;
+        let index_expr = field.index_expr;
+        let variant_index = position as u32;
+        let ty = field.ty;
+        let unwrap = get_unwrap(quote!(Self), &variant_name);
+        serialize_arms.push(quote!(
+            Self::#variant_name(x) => serializer.serialize_newtype_variant(#fieldtype_name, #variant_index, #field_name, x)
+        ));
+        deserialize_arms
+            .push(quote!(#position => variant.newtype_variant().map(Self::#variant_name)));
+        if field.is_fieldset {
+            let nested_fieldtype = quote!(<#ty as fieldset::FieldSetModel>::FieldType);
+            serialize_bounds
+                .push(quote!(#nested_fieldtype: fieldset::serde_support::serde::Serialize));
+            deserialize_bounds
+                .push(quote!(#nested_fieldtype: fieldset::serde_support::serde::Deserialize<'de>));
+            fields_bounds.push(quote!(#nested_fieldtype: fieldset::serde_support::SerializeFields));
+            lens.push(quote!(usize::from(
+                <#nested_fieldtype as fieldset::serde_support::SerializeFields>::serialized_len(
+                    &fieldset::Pending::nested(pending, #index_expr, #unwrap)
+                ) != 0
+            )));
+            entries.push(quote!(
+                let nested = fieldset::Pending::nested(pending, #index_expr, #unwrap);
+                if <#nested_fieldtype as fieldset::serde_support::SerializeFields>::serialized_len(&nested) != 0 {
+                    map.serialize_entry(#field_name, &fieldset::serde_support::SerializePending::new(nested))?;
+                }
+            ));
+        } else {
+            serialize_bounds.push(quote!(#ty: fieldset::serde_support::serde::Serialize));
+            deserialize_bounds.push(quote!(#ty: fieldset::serde_support::serde::Deserialize<'de>));
+            fields_bounds.push(quote!(#ty: fieldset::serde_support::serde::Serialize));
+            lens.push(quote!(usize::from(
+                fieldset::Pending::get(pending, #index_expr).and_then(#unwrap).is_some()
+            )));
+            entries.push(quote!(
+                if let Some(x) = fieldset::Pending::get(pending, #index_expr).and_then(#unwrap) {
+                    map.serialize_entry(#field_name, x)?;
+                }
+            ));
+        }
+        names.push(field_name);
+    }
+    let serialize_generics = get_serde_generics(generics.clone(), None, serialize_bounds);
+    let (serialize_impl_generics, _, serialize_where_clause) = serialize_generics.split_for_impl();
+    let deserialize_generics = get_serde_generics(
+        generics.clone(),
+        Some(parse_quote!('de)),
+        deserialize_bounds,
+    );
+    let (deserialize_impl_generics, _, deserialize_where_clause) =
+        deserialize_generics.split_for_impl();
+    let fields_generics = get_serde_generics(generics.clone(), None, fields_bounds);
+    let (fields_impl_generics, _, fields_where_clause) = fields_generics.split_for_impl();
+    let serialize_arms = match_borrowed(quote!(self), serialize_arms);
     quote!(
-        #[derive(Debug)]
-        struct #fieldset_identifier  {
-            bitset: fieldset::BitSet<{(#fieldset_variance + 31) / 32}>,
-            fields: [Option<#fieldtype_identifier> ; #fieldset_variance],
-            len: usize,
+        impl #serialize_impl_generics fieldset::serde_support::serde::Serialize for #fieldtype_identifier #ty_generics #serialize_where_clause {
+            fn serialize<__S: fieldset::serde_support::serde::Serializer>(&self, serializer: __S) -> Result<__S::Ok, __S::Error> {
+                #serialize_arms
+            }
         }
 
-        impl #fieldset_identifier {
-            pub fn new() -> Self {
-                Self {
-                    bitset: fieldset::BitSet::new(),
-                    fields: [() ; #fieldset_variance].map(|_| None),
-                    len: 0,
+        impl #deserialize_impl_generics fieldset::serde_support::serde::Deserialize<'de> for #fieldtype_identifier #ty_generics #deserialize_where_clause {
+            fn deserialize<__D: fieldset::serde_support::serde::Deserializer<'de>>(deserializer: __D) -> Result<Self, __D::Error> {
+                fieldset::serde_support::deserialize_field_type(deserializer)
+            }
+        }
+
+        impl #deserialize_impl_generics fieldset::serde_support::DeserializeFieldType<'de> for #fieldtype_identifier #ty_generics #deserialize_where_clause {
+            const NAME: &'static str = #fieldtype_name;
+            const NAMES: &'static [&'static str] = &[#( #names ),*];
+
+            fn deserialize_variant<__A: fieldset::serde_support::serde::de::VariantAccess<'de>>(field: usize, variant: __A) -> Result<Self, __A::Error> {
+                match field {
+                    #( #deserialize_arms ,)*
+                    _ => unreachable!(),
                 }
             }
         }
 
-        impl Default for #fieldset_identifier {
-            fn default() -> Self {
-                Self::new()
+        impl #fields_impl_generics fieldset::serde_support::SerializeFields for #fieldtype_identifier #ty_generics #fields_where_clause {
+            fn serialized_len<'__a, __P: fieldset::FieldLookup<'__a, Self>>(pending: &fieldset::Pending<__P>) -> usize
+            where
+                Self: '__a,
+            {
+                0 #( + #lens )*
+            }
+
+            fn serialize_fields<'__a, __P: fieldset::FieldLookup<'__a, Self>, __M: fieldset::serde_support::serde::ser::SerializeMap>(pending: &fieldset::Pending<__P>, map: &mut __M) -> Result<(), __M::Error>
+            where
+                Self: '__a,
+            {
+                #( #entries )*
+                Ok(())
+            }
+        }
+    )
+    .into()
+}
+
+#[cfg(feature = "serde")]
+fn derive_opt_fieldset_serde(
+    name: String,
+    generics: Generics,
+    fields: Vec<TrackedField>,
+) -> TokenStream {
+    let fieldset_identifier = format_ident!("{}OptFieldSet", name);
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let mut serialize_bounds = Vec::new();
+    let mut deserialize_bounds = Vec::new();
+    let mut names = Vec::new();
+    let mut lens = Vec::new();
+    let mut entries = Vec::new();
+    let mut deserialize_arms = Vec::new();
+    for (position, field) in fields.into_iter().enumerate() {
+        let field_identifier = field.method_name;
+        let field_name = field_identifier.unraw().to_string();
+        let ty = field.ty;
+        if field.is_fieldset {
+            let nested_fieldset = quote!(<#ty as fieldset::FieldSetModel>::OptFieldSet);
+            serialize_bounds
+                .push(quote!(#nested_fieldset: fieldset::serde_support::serde::Serialize));
+            deserialize_bounds
+                .push(quote!(#nested_fieldset: fieldset::serde_support::serde::Deserialize<'de>));
+            lens.push(quote!(usize::from(!self.#field_identifier.is_empty())));
+            entries.push(quote!(
+                if !self.#field_identifier.is_empty() {
+                    map.serialize_entry(#field_name, &self.#field_identifier)?;
+                }
+            ));
+            deserialize_arms.push(quote!(#position => {
+                self.#field_identifier = map.next_value()?;
+                Ok(())
+            }));
+        } else {
+            serialize_bounds.push(quote!(#ty: fieldset::serde_support::serde::Serialize));
+            deserialize_bounds.push(quote!(#ty: fieldset::serde_support::serde::Deserialize<'de>));
+            lens.push(quote!(usize::from(self.#field_identifier.is_some())));
+            entries.push(quote!(
+                if let Some(x) = &self.#field_identifier {
+                    map.serialize_entry(#field_name, x)?;
+                }
+            ));
+            deserialize_arms
+                .push(quote!(#position => {
+                    self.#field_identifier = Some(map.next_value()?);
+                    Ok(())
+                }));
+        }
+        names.push(field_name);
+    }
+    let serialize_generics = get_serde_generics(generics.clone(), None, serialize_bounds);
+    let (serialize_impl_generics, _, serialize_where_clause) = serialize_generics.split_for_impl();
+    let deserialize_generics = get_serde_generics(
+        generics.clone(),
+        Some(parse_quote!('de)),
+        deserialize_bounds,
+    );
+    let (deserialize_impl_generics, _, deserialize_where_clause) =
+        deserialize_generics.split_for_impl();
+    quote!(
+        impl #serialize_impl_generics fieldset::serde_support::serde::Serialize for #fieldset_identifier #ty_generics #serialize_where_clause {
+            fn serialize<__S: fieldset::serde_support::serde::Serializer>(&self, serializer: __S) -> Result<__S::Ok, __S::Error> {
+                use fieldset::serde_support::serde::ser::SerializeMap;
+                let mut map = serializer.serialize_map(Some(0 #( + #lens )*))?;
+                #( #entries )*
+                map.end()
+            }
+        }
+
+        impl #deserialize_impl_generics fieldset::serde_support::serde::Deserialize<'de> for #fieldset_identifier #ty_generics #deserialize_where_clause {
+            fn deserialize<__D: fieldset::serde_support::serde::Deserializer<'de>>(deserializer: __D) -> Result<Self, __D::Error> {
+                fieldset::serde_support::deserialize_fields(deserializer)
             }
         }
 
+        impl #deserialize_impl_generics fieldset::serde_support::DeserializeFields<'de> for #fieldset_identifier #ty_generics #deserialize_where_clause {
+            const NAMES: &'static [&'static str] = &[#( #names ),*];
+
+            fn deserialize_field<__A: fieldset::serde_support::serde::de::MapAccess<'de>>(&mut self, field: usize, map: &mut __A) -> Result<(), __A::Error> {
+                match field {
+                    #( #deserialize_arms ,)*
+                    _ => unreachable!(),
+                }
+            }
+        }
     )
     .into()
 }
 
-fn derive_perf_fieldset(name: String, _fields: FieldsNamed) -> TokenStream {
+#[cfg(feature = "serde")]
+fn derive_perf_fieldset_serde(
+    name: String,
+    generics: Generics,
+    _fields: Vec<TrackedField>,
+) -> TokenStream {
     let identifier = format_ident!("{}", name);
     let fieldset_identifier = format_ident!("{}PerfFieldSet", name);
+    let opt_fieldset_identifier = format_ident!("{}OptFieldSet", name);
     let fieldtype_identifier = format_ident!("{}FieldType", name);
-    let fieldset_variance = get_variance_identifier(identifier);
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let serialize_generics = get_serde_generics(
+        generics.clone(),
+        None,
+        vec![quote!(#fieldtype_identifier #ty_generics: fieldset::serde_support::SerializeFields)],
+    );
+    let (serialize_impl_generics, _, serialize_where_clause) = serialize_generics.split_for_impl();
+    let deserialize_generics = get_serde_generics(
+        generics.clone(),
+        Some(parse_quote!('de)),
+        vec![
+            quote!(#opt_fieldset_identifier #ty_generics: fieldset::serde_support::serde::Deserialize<'de>),
+        ],
+    );
+    let (deserialize_impl_generics, _, deserialize_where_clause) =
+        deserialize_generics.split_for_impl();
     quote!(
-        #[derive(Debug)]
-        pub struct #fieldset_identifier  {
-            bitset: [u16 ; #fieldset_variance],
-            fields: [Option<#fieldtype_identifier> ; #fieldset_variance],
-            len: usize,
-        }
-
-        impl #fieldset_identifier {
-            pub fn new() -> Self {
-                Self {
-                    bitset: [() ; #fieldset_variance].map(|_| 0),
-                    fields: [() ; #fieldset_variance].map(|_| None),
-                    len: 0,
-                }
+        impl #serialize_impl_generics fieldset::serde_support::serde::Serialize for #fieldset_identifier #ty_generics #serialize_where_clause {
+            fn serialize<__S: fieldset::serde_support::serde::Serializer>(&self, serializer: __S) -> Result<__S::Ok, __S::Error> {
+                fieldset::serde_support::serde::Serialize::serialize(
+                    &fieldset::serde_support::SerializePending::<_, #fieldtype_identifier #ty_generics>::new(self.pending()),
+                    serializer,
+                )
             }
         }
 
-        impl Default for #fieldset_identifier {
-            fn default() -> Self {
-                Self::new()
+        // The maps of both storages share a layout, so the modifications are collected through
+        // the `OptFieldSet` and replayed in declaration order.
+        impl #deserialize_impl_generics fieldset::serde_support::serde::Deserialize<'de> for #fieldset_identifier #ty_generics #deserialize_where_clause {
+            fn deserialize<__D: fieldset::serde_support::serde::Deserializer<'de>>(deserializer: __D) -> Result<Self, __D::Error> {
+                let fields: #opt_fieldset_identifier #ty_generics = fieldset::serde_support::serde::Deserialize::deserialize(deserializer)?;
+                let mut res = Self::new();
+                for field in fields {
+                    let index = <#identifier #ty_generics as fieldset::FieldSetModel>::field_index(&field);
+                    fieldset::FieldSink::put(&mut res, index, field);
+                }
+                Ok(res)
             }
         }
     )
@@ -605,81 +2692,283 @@ fn derive_perf_fieldset(name: String, _fields: FieldsNamed) -> TokenStream {
 #[proc_macro_derive(FieldSet, attributes(fieldset, fieldset_skip))]
 pub fn derive_fieldset(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
-    if let syn::Data::Struct(ref data) = input.data {
-        if let syn::Fields::Named(ref fields) = data.fields {
-            let mut result = TokenStream::default();
-            result.extend(derive_field_type(input.ident.to_string(), fields.clone()));
-            result.extend(derive_into_iterator(
-                input.ident.to_string(),
-                fields.clone(),
-            ));
-            result.extend(derive_setter_trait(input.ident.to_string(), fields.clone()));
-            result.extend(derive_fieldset_variance(
-                input.ident.to_string(),
-                fields.clone(),
-            ));
-            result.extend(derive_raw_fieldset_setter_trait_impl(
-                input.ident.to_string(),
-                fields.clone(),
-            ));
-            result.extend(derive_opt_fieldset_type(
-                input.ident.to_string(),
-                fields.clone(),
-            ));
-            result.extend(derive_opt_fieldset_setter_trait_impl(
-                input.ident.to_string(),
-                fields.clone(),
-            ));
-            result.extend(derive_opt_fieldset_into_iterator(
-                input.ident.to_string(),
-                fields.clone(),
-            ));
-            result.extend(derive_bitset_fieldset(
-                input.ident.to_string(),
-                fields.clone(),
-            ));
-            result.extend(derive_common_fieldset_setter_trait_impl(
-                true,
-                input.ident.to_string(),
-                fields.clone(),
-            ));
-            result.extend(derive_common_fieldset_trait_impl(
-                true,
-                input.ident.to_string(),
-                fields.clone(),
-            ));
-            result.extend(derive_common_fieldset_into_iterator(
-                true,
-                input.ident.to_string(),
-                fields.clone(),
-            ));
-            result.extend(derive_perf_fieldset(
-                input.ident.to_string(),
+    let name = input.ident.to_string();
+    let generics = input.generics.clone();
+    let options = match get_model_options(input.attrs.clone()) {
+        Ok(options) => options,
+        Err(e) => return TokenStream::from(e.to_compile_error()),
+    };
+    let derives = options.derives.clone();
+    if options.codec && matches!(input.data, syn::Data::Enum(_)) {
+        return TokenStream::from(
+            syn::Error::new(
+                input.ident.span(),
+                "`#[fieldset(codec)]` is not supported on enums, whose variant switches hold the whole enum",
+            )
+            .to_compile_error(),
+        );
+    }
+    let fields = match input.data {
+        syn::Data::Struct(ref data) if !matches!(data.fields, Fields::Unit) => {
+            Some(get_tracked_fields(data.fields.clone()))
+        }
+        syn::Data::Enum(ref data) => Some(get_tracked_variant_fields(
+            name.clone(),
+            generics.clone(),
+            data.variants.clone().into_iter().collect(),
+        )),
+        _ => None,
+    };
+    let fields = match fields.transpose() {
+        Ok(fields) => fields,
+        Err(e) => return TokenStream::from(e.to_compile_error()),
+    };
+    if let Some(fields) = fields {
+        let mut result = TokenStream::default();
+        result.extend(derive_field_type(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+            derives.clone(),
+        ));
+        result.extend(derive_field_id(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_into_iterator(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_setter_trait(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_getter_trait(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_field_table(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_field_paths(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_fieldset_model(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_fieldset_diff(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+            derives.clone(),
+        ));
+        result.extend(derive_fieldset_dedup(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+            derives.clone(),
+        ));
+        result.extend(derive_fieldset_read(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+            derives.clone(),
+        ));
+        result.extend(derive_fieldset_undo(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_fieldset_validate(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+            options.validate.clone(),
+            derives.clone(),
+        ));
+        if options.codec {
+            result.extend(derive_field_type_codec(
+                name.clone(),
+                generics.clone(),
                 fields.clone(),
             ));
-            result.extend(derive_common_fieldset_setter_trait_impl(
-                false,
-                input.ident.to_string(),
+        }
+        result.extend(derive_raw_fieldset_sink_impl(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_opt_fieldset_type(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+            derives.clone(),
+        ));
+        result.extend(derive_opt_fieldset_sink_impl(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_opt_fieldset_from_model(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_opt_fieldset_into_iterator(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+            derives.clone(),
+        ));
+        result.extend(derive_opt_fieldset_methods(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+            derives.clone(),
+        ));
+        result.extend(derive_opt_fieldset_queries(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        if options.serde {
+            #[cfg(feature = "serde")]
+            result.extend(derive_field_type_serde(
+                name.clone(),
+                generics.clone(),
                 fields.clone(),
             ));
-            result.extend(derive_common_fieldset_trait_impl(
-                false,
-                input.ident.to_string(),
+            #[cfg(feature = "serde")]
+            result.extend(derive_opt_fieldset_serde(
+                name.clone(),
+                generics.clone(),
                 fields.clone(),
             ));
-            result.extend(derive_common_fieldset_into_iterator(
-                false,
-                input.ident.to_string(),
+        }
+        result.extend(derive_bitset_fieldset(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+            derives.clone(),
+        ));
+        result.extend(derive_common_fieldset_sink_impl(
+            Storage::Bit,
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_common_fieldset_into_iterator(
+            Storage::Bit,
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+            derives.clone(),
+        ));
+        result.extend(derive_common_fieldset_methods(
+            Storage::Bit,
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_common_fieldset_queries(
+            Storage::Bit,
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_perf_fieldset(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+            derives.clone(),
+            options.perf_index.clone(),
+        ));
+        result.extend(derive_common_fieldset_sink_impl(
+            Storage::Perf,
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_common_fieldset_into_iterator(
+            Storage::Perf,
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+            derives.clone(),
+        ));
+        result.extend(derive_common_fieldset_methods(
+            Storage::Perf,
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_common_fieldset_queries(
+            Storage::Perf,
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_index_fieldset(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+            derives.clone(),
+        ));
+        result.extend(derive_common_fieldset_sink_impl(
+            Storage::Index,
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_common_fieldset_into_iterator(
+            Storage::Index,
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+            derives.clone(),
+        ));
+        result.extend(derive_common_fieldset_methods(
+            Storage::Index,
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_common_fieldset_queries(
+            Storage::Index,
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        result.extend(derive_field_mask(
+            name.clone(),
+            generics.clone(),
+            fields.clone(),
+        ));
+        if options.serde {
+            #[cfg(feature = "serde")]
+            result.extend(derive_perf_fieldset_serde(
+                name.clone(),
+                generics.clone(),
                 fields.clone(),
             ));
-            return result;
         }
+        return result;
     }
 
     TokenStream::from(
         syn::Error::new(
             input.ident.span(),
-            "Only structs with named fields can derive `FieldEvents`",
+            "Only enums and structs with named or unnamed fields can derive `FieldSet`",
         )
         .to_compile_error(),
     )
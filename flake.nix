@@ -18,12 +18,12 @@
       ];
 
       buildToolchain = system: with fenix.packages.${system}; combine [
-        latest.cargo
-        latest.rustc
-        latest.clippy
-        latest.llvm-tools
-        latest.rustfmt
-        latest.rust-analyzer
+        stable.cargo
+        stable.rustc
+        stable.clippy
+        stable.llvm-tools
+        stable.rustfmt
+        stable.rust-analyzer
       ];
     in
     {
@@ -45,6 +45,7 @@
               cargoLock = {
                 lockFile = ./Cargo.lock;
               };
+              cargoTestFlags = [ "--all-features" ];
             };
           });
 
@@ -1,4 +1,4 @@
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct BitSet<const N_32: usize> {
     bits: [u32; N_32],
 }
@@ -21,9 +21,9 @@ impl<'a> BitSetOffsetted<'a> {
         self.bits[self.idx(i) / 32] &= !(1 << (self.idx(i) % 32));
     }
 
-    pub fn offset(&mut self, offset: usize) -> BitSetOffsetted {
+    pub fn offset(&mut self, offset: usize) -> BitSetOffsetted<'_> {
         BitSetOffsetted {
-            bits: &mut self.bits,
+            bits: self.bits,
             offset: self.offset + offset,
         }
     }
@@ -33,11 +33,44 @@ impl<'a> BitSetOffsetted<'a> {
     }
 }
 
+impl<const N_32: usize> Default for BitSet<N_32> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<const N_32: usize> BitSet<N_32> {
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
         Self { bits: [0; N_32] }
     }
 
+    /// Sets the bits of `start..end`.
+    pub const fn with_range(mut self, start: usize, end: usize) -> Self {
+        let mut i = start;
+        while i < end {
+            self.bits[i / 32] |= 1 << (i % 32);
+            i += 1;
+        }
+        self
+    }
+
+    pub const fn union(mut self, other: Self) -> Self {
+        let mut word = 0;
+        while word < N_32 {
+            self.bits[word] |= other.bits[word];
+            word += 1;
+        }
+        self
+    }
+
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.bits.iter().zip(&other.bits).any(|(a, b)| a & b != 0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|bits| *bits == 0)
+    }
+
     pub fn test(&self, i: usize) -> bool {
         self.bits[i / 32] & (1 << (i % 32)) != 0
     }
@@ -50,10 +83,25 @@ impl<const N_32: usize> BitSet<N_32> {
         }
     }
 
-    pub fn offset(&mut self, offset: usize) -> BitSetOffsetted {
+    pub fn offset(&mut self, offset: usize) -> BitSetOffsetted<'_> {
         BitSetOffsetted {
             bits: &mut self.bits,
             offset,
         }
     }
+
+    /// Index of the first set bit at or after `i`.
+    pub fn next_set(&self, i: usize) -> Option<usize> {
+        let mut word = i / 32;
+        let mut mask = u32::MAX << (i % 32);
+        while let Some(bits) = self.bits.get(word) {
+            let bits = bits & mask;
+            if bits != 0 {
+                return Some(word * 32 + bits.trailing_zeros() as usize);
+            }
+            word += 1;
+            mask = u32::MAX;
+        }
+        None
+    }
 }
@@ -0,0 +1,151 @@
+/// Error of the wire codec derived with `#[fieldset(codec)]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodecError {
+    /// The buffer ended before the field was fully encoded or decoded.
+    BufferTooShort,
+    /// The decoded tag is not the index of a field of the model.
+    InvalidTag(usize),
+    /// The decoded bytes are not a valid value of the field.
+    InvalidValue,
+}
+
+/// Fixed-size little-endian wire encoding of field values.
+///
+/// Implemented for the primitive types and arrays of them. Values of other types can implement it
+/// to be encoded by models deriving `#[fieldset(codec)]`.
+pub trait FieldCodec: Sized {
+    const SIZE: usize;
+
+    /// Encodes the value into `buf`, which is exactly `SIZE` bytes long.
+    fn encode(&self, buf: &mut [u8]);
+
+    /// Decodes a value from `buf`, which is exactly `SIZE` bytes long.
+    fn decode(buf: &[u8]) -> Option<Self>;
+}
+
+macro_rules! impl_field_codec {
+    ($($ty:ty),*) => {
+        $(
+            impl FieldCodec for $ty {
+                const SIZE: usize = core::mem::size_of::<$ty>();
+
+                fn encode(&self, buf: &mut [u8]) {
+                    buf.copy_from_slice(&self.to_le_bytes());
+                }
+
+                fn decode(buf: &[u8]) -> Option<Self> {
+                    Some(Self::from_le_bytes(buf.try_into().ok()?))
+                }
+            }
+        )*
+    };
+}
+
+impl_field_codec!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+impl FieldCodec for bool {
+    const SIZE: usize = 1;
+
+    fn encode(&self, buf: &mut [u8]) {
+        buf[0] = u8::from(*self);
+    }
+
+    fn decode(buf: &[u8]) -> Option<Self> {
+        match buf[0] {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl FieldCodec for char {
+    const SIZE: usize = 4;
+
+    fn encode(&self, buf: &mut [u8]) {
+        u32::from(*self).encode(buf)
+    }
+
+    fn decode(buf: &[u8]) -> Option<Self> {
+        char::from_u32(u32::decode(buf)?)
+    }
+}
+
+impl<T: FieldCodec, const N: usize> FieldCodec for [T; N] {
+    const SIZE: usize = T::SIZE * N;
+
+    fn encode(&self, buf: &mut [u8]) {
+        // Sliced by index rather than in chunks, which cannot be empty for zero-sized values.
+        for (i, value) in self.iter().enumerate() {
+            value.encode(&mut buf[i * T::SIZE..(i + 1) * T::SIZE]);
+        }
+    }
+
+    fn decode(buf: &[u8]) -> Option<Self> {
+        let values: [Option<T>; N] =
+            core::array::from_fn(|i| T::decode(&buf[i * T::SIZE..(i + 1) * T::SIZE]));
+        if values.iter().any(Option::is_none) {
+            return None;
+        }
+        Some(values.map(Option::unwrap))
+    }
+}
+
+/// Implemented by the derived `FieldType` to encode its value without the tag, so that nested
+/// models can be encoded by their parent.
+#[doc(hidden)]
+pub trait FieldTypeCodec: Sized {
+    fn encode_value(&self, buf: &mut [u8]) -> Result<usize, CodecError>;
+
+    /// Decodes the value of the field at the flat `index` of the model.
+    fn decode_value(index: usize, buf: &[u8]) -> Result<(Self, usize), CodecError>;
+}
+
+/// Encodes the flat index of a field as a LEB128 varint.
+#[doc(hidden)]
+pub fn encode_tag(index: usize, buf: &mut [u8]) -> Result<usize, CodecError> {
+    let mut index = index;
+    let mut len = 0;
+    loop {
+        let byte = buf.get_mut(len).ok_or(CodecError::BufferTooShort)?;
+        len += 1;
+        if index < 0x80 {
+            *byte = index as u8;
+            return Ok(len);
+        }
+        *byte = (index as u8) | 0x80;
+        index >>= 7;
+    }
+}
+
+#[doc(hidden)]
+pub fn decode_tag(buf: &[u8]) -> Result<(usize, usize), CodecError> {
+    let mut index: usize = 0;
+    for (len, byte) in buf.iter().enumerate() {
+        let shift = 7 * len as u32;
+        let bits = usize::from(byte & 0x7f);
+        // Tags that do not fit in a `usize` cannot index any field.
+        if shift >= usize::BITS || (bits << shift) >> shift != bits {
+            return Err(CodecError::InvalidTag(usize::MAX));
+        }
+        index |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok((index, len + 1));
+        }
+    }
+    Err(CodecError::BufferTooShort)
+}
+
+#[doc(hidden)]
+pub fn encode_value<T: FieldCodec>(value: &T, buf: &mut [u8]) -> Result<usize, CodecError> {
+    let buf = buf.get_mut(..T::SIZE).ok_or(CodecError::BufferTooShort)?;
+    value.encode(buf);
+    Ok(T::SIZE)
+}
+
+#[doc(hidden)]
+pub fn decode_value<T: FieldCodec>(buf: &[u8]) -> Result<(T, usize), CodecError> {
+    let buf = buf.get(..T::SIZE).ok_or(CodecError::BufferTooShort)?;
+    let value = T::decode(buf).ok_or(CodecError::InvalidValue)?;
+    Ok((value, T::SIZE))
+}
@@ -0,0 +1,192 @@
+use core::borrow::Borrow;
+use core::ops::Range;
+
+use crate::FieldSetModel;
+
+/// Typed selection of a field or a whole nested model within the modifications of a model.
+///
+/// The derived `Path` type provides a `FieldPath` constant for each field of its model, such as
+/// `DomainModelPath::SUB`, which are chained into nested models with `then`.
+pub trait FieldSelector<T> {
+    type Value;
+
+    /// Range of the selected fields in the flattened `0..VARIANCE` layout.
+    fn fields(&self) -> Range<usize>;
+
+    /// Calls `handler` with the payload of `field`, if it is one of the selected fields.
+    fn select(&self, field: &T, handler: &mut dyn FnMut(&Self::Value));
+
+    fn then<S: FieldSelector<Self::Value>>(self, nested: S) -> Then<Self, S>
+    where
+        Self: Sized,
+    {
+        Then(self, nested)
+    }
+}
+
+/// Selection of a direct field of a model, derived as the constants of its `Path` type.
+pub struct FieldPath<T, V> {
+    fields: Range<usize>,
+    unwrap: fn(&T) -> Option<&V>,
+}
+
+impl<T, V> FieldPath<T, V> {
+    #[doc(hidden)]
+    pub const fn new(fields: Range<usize>, unwrap: fn(&T) -> Option<&V>) -> Self {
+        Self { fields, unwrap }
+    }
+}
+
+impl<T, V> FieldSelector<T> for FieldPath<T, V> {
+    type Value = V;
+
+    fn fields(&self) -> Range<usize> {
+        self.fields.clone()
+    }
+
+    fn select(&self, field: &T, handler: &mut dyn FnMut(&V)) {
+        if let Some(value) = (self.unwrap)(field) {
+            handler(value);
+        }
+    }
+}
+
+/// Selection of a field of a nested model, built with `FieldSelector::then`.
+pub struct Then<S, N>(S, N);
+
+impl<T, S: FieldSelector<T>, N: FieldSelector<S::Value>> FieldSelector<T> for Then<S, N> {
+    type Value = N::Value;
+
+    fn fields(&self) -> Range<usize> {
+        let offset = self.0.fields().start;
+        let nested = self.1.fields();
+        offset + nested.start..offset + nested.end
+    }
+
+    fn select(&self, field: &T, handler: &mut dyn FnMut(&N::Value)) {
+        self.0
+            .select(field, &mut |field| self.1.select(field, handler))
+    }
+}
+
+/// Set of fields of a model, implemented by the derived `FieldId` and `Mask` types.
+pub trait FieldMask {
+    /// Whether the field at `index` in the flattened `0..VARIANCE` layout is in the set.
+    fn contains(&self, index: usize) -> bool;
+}
+
+/// Subscriber of a `Dispatcher` to the modifications of some fields of a model.
+pub trait FieldHandler<T> {
+    /// Whether the field at `index` in the flattened `0..VARIANCE` layout is handled.
+    fn contains(&self, index: usize) -> bool;
+
+    fn handle(&mut self, field: &T);
+}
+
+/// Handler that receives the typed payload of the fields selected by `S`.
+pub struct Subscription<S, F> {
+    selector: S,
+    handler: F,
+}
+
+impl<S, F> Subscription<S, F> {
+    pub fn new(selector: S, handler: F) -> Self {
+        Self { selector, handler }
+    }
+}
+
+impl<T, S: FieldSelector<T>, F: FnMut(&S::Value)> FieldHandler<T> for Subscription<S, F> {
+    fn contains(&self, index: usize) -> bool {
+        self.selector.fields().contains(&index)
+    }
+
+    fn handle(&mut self, field: &T) {
+        self.selector.select(field, &mut self.handler);
+    }
+}
+
+/// Handler that receives the whole modification of the fields in a `FieldMask`, such as a
+/// `FieldId` or a union of `Mask` constants.
+pub struct MaskSubscription<K, F> {
+    mask: K,
+    handler: F,
+}
+
+impl<K, F> MaskSubscription<K, F> {
+    pub fn new(mask: K, handler: F) -> Self {
+        Self { mask, handler }
+    }
+}
+
+impl<T, K: FieldMask, F: FnMut(&T)> FieldHandler<T> for MaskSubscription<K, F> {
+    fn contains(&self, index: usize) -> bool {
+        self.mask.contains(index)
+    }
+
+    fn handle(&mut self, field: &T) {
+        (self.handler)(field);
+    }
+}
+
+/// Error of a `Dispatcher` that has no room for another subscriber.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DispatcherFull;
+
+/// Fixed-capacity registry of up to `N` subscribers to the modifications of a model.
+///
+/// Each modification of a dispatched batch is handed to the subscribers of its field only.
+pub struct Dispatcher<'a, M: FieldSetModel, const N: usize> {
+    handlers: [Option<&'a mut dyn FieldHandler<M::FieldType>>; N],
+    len: usize,
+}
+
+impl<'a, M: FieldSetModel, const N: usize> Dispatcher<'a, M, N> {
+    pub fn new() -> Self {
+        Self {
+            handlers: [(); N].map(|_| None),
+            len: 0,
+        }
+    }
+
+    pub fn subscribe(
+        &mut self,
+        handler: &'a mut dyn FieldHandler<M::FieldType>,
+    ) -> Result<(), DispatcherFull> {
+        let slot = self.handlers.get_mut(self.len).ok_or(DispatcherFull)?;
+        *slot = Some(handler);
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Hands every modification of `fields`, by value or by reference, to its subscribers in
+    /// the order they subscribed.
+    pub fn dispatch<I>(&mut self, fields: I)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<M::FieldType>,
+    {
+        for field in fields {
+            let field = field.borrow();
+            let index = M::field_index(field);
+            for handler in self.handlers[..self.len].iter_mut().flatten() {
+                if handler.contains(index) {
+                    handler.handle(field);
+                }
+            }
+        }
+    }
+}
+
+impl<'a, M: FieldSetModel, const N: usize> Default for Dispatcher<'a, M, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
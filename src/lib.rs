@@ -20,13 +20,117 @@
 //! - `OptFieldSet` is backed by a derived struct where each field is converted to an `Option`. Each iteration goes through all fields and is therefore suitable for smaller structures or frequent modifications.
 //! - `BitFieldSet` is backed by an iteration array of `FieldType` with length equal to the number of fields, and a `bitset` that tracks which fields have been modified. Iteration is optimal and only goes through exactly as many fields as were modified. Has the drawback that each field can only be modified once before iteration and subsequent modifications are ignored. This is often a good compromise.
 //! - `PerfFieldSet` is backed by an array of `FieldType` of length equal to the number of fields and a complementary array that tracks which fields have been modified and their current position in the iteration array. Iteration is optimal and only goes through exactly as many fields as were modified. Fields can be modified multiple times and only the latest modification applies. Has the drawback of the extra space needed to track the multiple modifications.
+//! - `IndexFieldSet` is backed by an array of `FieldType` where each modification is stored at the index of its field, and a `bitfield` that tracks which fields have been modified. Fields can be modified multiple times and only the latest modification applies, as in `PerfFieldSet`, with the footprint of `BitFieldSet`. Iteration goes through the set bits and yields the modified fields in declaration order rather than in modification order.
 //!
-//! The library currently requires the usage of the nightly `impl_trait_in_assoc_type` feature.
+//! `PerfFieldSet` tracks the position of each field with the narrowest of `u8`, `u16` and `u32` that fits the number of fields,
+//! which can be overridden with `#[fieldset(perf_index = u16)]`. Models with more fields than the selected type can track fail to compile.
+//!
+//! `BitFieldSet` and `PerfFieldSet` iterate in the order in which the fields were first modified. `sort()` reorders their pending modifications in place into declaration order,
+//! such as for writing hardware registers deterministically, while `IndexFieldSet` and `OptFieldSet` always iterate in declaration order.
+//!
+//! `LogFieldSet<FieldType, N>` is an append-only log that keeps every modification in the order it was made, for audit trails and replay.
+//! Its capacity `N` is independent of the number of fields, and it is created with an overflow policy: `LogOverflow::DropOldest`, `LogOverflow::DropNewest`,
+//! or `LogOverflow::Error` which rejects the modifications that do not fit and reports them through `check()`.
+//! The modifications discarded by any policy are counted by `dropped()`.
+//!
+//! `FieldSet` types can be reused instead of being rebuilt for every batch of modifications. `iter()` iterates without consuming the set,
+//! `drain()` yields the modifications while resetting the set in place and `clear()` discards them. `BitFieldSet`, `PerfFieldSet` and `IndexFieldSet` only reset the slots of the pending modifications
+//! and iterate by reference, while `OptFieldSet::iter()` yields clones and is available when the `FieldType` derives `Clone`.
+//!
+//! A batch of modifications can also be inspected before it is applied. Every `FieldSet` provides `len()`, `is_empty()` and `is_modified()`,
+//! which takes the derived payload-free `FieldId` enum, while `pending()` returns a view implementing the derived `FieldGetter` trait
+//! that returns the pending value of each field, such as `field_set.pending().sub().b()` returning an `Option<&u32>`.
+//! These methods would hide setters of the same name, as would those of the other types implementing the setter traits, `LogFieldSet`, `SharedFieldSet`, the `Producer` of a `ChangeQueue` and the model.
+//! Fields named `apply`, `put`, `into_iter`, `iter`, `drain`, `clear`, `sort`, `len`, `is_empty`, `is_modified`, `is_any_modified`, `pending`, `push`, `check`, `dropped`, `capacity`,
+//! `take`, `with`, `try_apply_all`, `apply_with_undo` or `apply_with_inverse` therefore fail to derive and their setters are renamed with `#[fieldset(name = ...)]`.
+//!
+//! Modifications can also be computed from two instances received wholesale, e.g. from a driver or a configuration reload.
+//! `DomainModel::diff(&old, &new, &mut field_set)` calls the setter of every field that differs, recursing into nested models,
+//! and switches the variant of enum models whose active variant changed. It is provided when the `FieldType` derives `Clone` and `PartialEq`.
+//!
+//! Writes of the value a field already has can be filtered out by wrapping a `BitFieldSet` or `PerfFieldSet` in `DedupFieldSetter::new(&model, &mut field_set)`,
+//! which implements the same `FieldSetter` traits but only records the modifications that change the current model, across nested models.
+//! Writes to the fields of an inactive enum variant, which would be dropped when applied, are skipped as well unless a variant switch is pending.
+//! Fields are compared with `PartialEq`, with a function through `#[fieldset(eq = path)]` or within a tolerance through `#[fieldset(epsilon = 1e-3)]`.
+//! Models whose `FieldType` does not derive `PartialEq` opt in through these annotations, which every field without `PartialEq` then needs.
+//!
+//! Setters are write-only, unless they are layered over the current model with `LayeredFieldSetter::new(&model, &mut field_set)`.
+//! Its leaf setters, as well as those of `DedupFieldSetter`, provide `get()` returning the effective value of the field, pending if modified and current otherwise,
+//! and `update(|v| ...)`, such as `setter.sub().b().update(|b| b + 1)`. This requires the `FieldType` to derive `Clone`.
+//!
+//! Modifications can be rolled back. `model.apply_with_undo(field)` returns the modification that restores the previous value,
+//! and `model.apply_with_inverse(fields, &mut inverse)` applies a batch while collecting its inverse into an empty `BitFieldSet` or `PerfFieldSet`,
+//! which restores the model as it was when applied in turn. Both are provided by the derived `FieldSetUndo` implementation.
+//!
+//! Batches can be applied transactionally with `model.try_apply_all(field_set)`, which commits every modification of an `OptFieldSet`, `BitFieldSet` or `PerfFieldSet`
+//! or leaves the model untouched and returns the `FieldId` that failed validation. Fields are validated with `#[fieldset(validate = path)]` taking `&T` and returning a `bool`,
+//! and the model with the batch applied by a `#[fieldset(validate = path)]` on the model itself, taking `&Model` and returning a `Result<(), ModelFieldId>`, and `try_apply_all` is provided when the `FieldType` derives `Clone`.
+//!
+//! Batches can be routed to the subsystems interested in them with a fixed-capacity `Dispatcher<Model, N>`. Each model derives a `DomainModelPath` with a `FieldPath` constant per field,
+//! such as `DomainModelPath::C` or `DomainModelPath::SUB` for a whole nested model, which are chained into nested models with `then`
+//! such as `DomainModelPath::SUB.then(SubModelPath::B)`. `Subscription::new(path, |b: &u32| ...)` handles the typed payload of the selected fields,
+//! while `MaskSubscription::new(mask, |field| ...)` handles the whole `FieldType` of the fields in a `FieldId` or a `Mask`,
+//! and `dispatcher.dispatch(field_set.iter())` hands each modification to its subscribers only.
+//!
+//! Subsystems can check whether anything they depend on was modified without iterating. Each model derives a `DomainModelMask` of its flattened fields,
+//! with a constant per field that covers whole nested models, such as `DomainModelMask::SUB | DomainModelMask::C` or `DomainModelMask::SUB.union(DomainModelMask::C)` in constants,
+//! and the `BitFieldSet`, `IndexFieldSet` and `PerfFieldSet` provide `is_any_modified(&mask)`, which compares the mask with the `bitfield` of the first two word by word
+//! and checks the position of each field of the mask in the `PerfFieldSet`. `DomainModelMask::empty()` and `DomainModelMask::all()` cover no field and every field.
+//!
+//! With the `async` feature, a `Watch<Model, DomainModelPerfFieldSet, R>` owns a model and the last batch committed to it through `watch.commit(|setter| ...)`,
+//! for the tasks of a single-threaded executor without alloc. Up to `R` receivers await the next commit with `receiver.changed().await`,
+//! or the next modifications for which a closure returns a value, such as `receiver.next(|batch| batch.is_any_modified(&MASK).then_some(())).await`.
+//! Each receiver is handed the modifications committed since it last polled, merged into one batch, so it does not miss the batches committed in between.
+//!
+//! With the `critical-section` feature, a `SharedFieldSet<DomainModelPerfFieldSet>` can be modified through `&self`, such as from an interrupt handler
+//! and the main loop at once. `&SharedFieldSet` implements the derived `FieldSetter` traits, guarding every modification with a critical section,
+//! and `take()` swaps the pending batch with a second preallocated storage, so that critical sections last for a single modification or swap.
+//! The taken batch is reset outside of any critical section when dropped. It can be created in a `static` with `SharedFieldSet::new(DomainModelPerfFieldSet::new(), DomainModelPerfFieldSet::new())`.
+//!
+//! `ChangeQueue<FieldType, N>` streams modifications from an interrupt handler to a task instead of batching them. `split()` returns a `Producer`
+//! implementing the derived `FieldSetter` traits and a `Consumer` iterating the modifications in the order they were made, using only atomic loads and stores.
+//! Modifications that do not fit are dropped and counted by `Consumer::take_dropped()`.
+//! A queue in a `static` is split once with `split_static()`, which returns `None` on later calls.
+//!
+//! Each `FieldType` and `FieldId` knows its flat `index()`, its `name()` and its dotted `path()` such as `"sub.b"`,
+//! and the model exposes the same metadata for all of its fields as `const FIELDS: [FieldInfo; VARIANCE]`, e.g. for logging or telemetry.
+//!
+//! With the `serde` feature, models annotated with `#[fieldset(serde)]` get a `FieldType` that implements `Serialize` and `Deserialize`,
+//! externally tagged with the field name such as `{"sub":{"b":1}}`, and `OptFieldSet` and `PerfFieldSet` are (de)serialized as sparse maps
+//! of their pending modifications such as `{"c":1.0,"sub":{"b":1}}`. Their tracked fields must implement the serde traits, and their `#[fieldset]`
+//! fields must be annotated as well.
+//!
+//! Models annotated with `#[fieldset(codec)]` get a compact binary wire codec without alloc or serde. `FieldType::encode(&mut buf)` writes the flat index
+//! of the field as a varint tag followed by the value in a fixed little-endian encoding, and `FieldType::decode(&buf)` returns the modification
+//! along with the number of bytes read, so a batch can be streamed field by field. Values are encoded through the `FieldCodec` trait,
+//! which is implemented for the primitive types and arrays of them, and nested `#[fieldset]` models must opt into the codec too.
+//! Enum models do not support the codec, as their variant switches hold the whole enum.
+//!
+//! Generic structs are supported and their type, lifetime and const parameters are carried over to every derived item.
+//! A generic model nested in another, such as a `Sensor<T: Num>` field of a `Board<T: Num>`, is sized through an instance of its own,
+//! so when `()` does not satisfy its bounds the field names one with `#[fieldset(layout = Sensor<f32>)]`, as described for nested fields below.
+//!
+//! The `FieldType` derives `Clone`, `Copy`, `Debug` and `PartialEq` by default, which requires every tracked field to implement them.
+//! Models with other field types, such as move-only payloads, can select the derived traits with `#[fieldset(derive(Debug, PartialEq))]`.
+//! The `Clone` and `Debug` derives are forwarded to the derived iterators and `Debug` to the `FieldSet` types.
+//!
+//! Tuple structs are supported as well. Their fields map to `_0`, `_1`, ... variants and setter methods, which can be renamed with `#[fieldset(name = ...)]`.
+//!
+//! Enums can derive `FieldSet` too. Their `FieldType` has a `Variant` variant that switches the enum to a new value, set through the `variant()` setter,
+//! and one variant per field of each enum variant, such as `RunningSpeed` and `running_speed()` for `Running { speed }` or `Fault0` and `fault_0()` for `Fault(u8)`.
+//! Field modifications only apply while their variant is active. In every storage, a variant switch discards the pending field
+//! modifications of the enum, unless it is itself ignored because a `BitFieldSet` already holds a switch. Enum models must implement the traits derived for their `FieldType`.
+//!
+//! Nested `#[fieldset]` fields are linked to their model through the `FieldSetModel` trait, so the nested model can be referred to by any path,
+//! including models living in other modules or crates and generic instances such as `Wrapper<u8>`.
+//! Storages size their arrays with the fields of nested models whose type parameters are replaced by `()`, which must therefore satisfy their bounds.
+//! Otherwise the field names an instance that does with `#[fieldset(layout = Sensor<f32>)]` in place of `#[fieldset]`, as every instance of a model has the same fields.
+//!
+//! Every `FieldSet` iterates through a named iterator type, such as `DomainModelOptFieldSetIter` or `DomainModelPerfFieldSetIter`, so the library works on stable Rust.
 //!
 //! # Example
 //!
 //! ```rust
-//! #![feature(impl_trait_in_assoc_type)]
 //! use fieldset::{FieldSetter, FieldSet};
 //!
 //! #[derive(Default, FieldSet)]
@@ -56,6 +160,7 @@
 //!     for i in 0..10 {
 //!         let mut field_set = DomainModelPerfFieldSet::default();
 //!         modifier(&mut field_set, i);
+//!         assert_eq!(field_set.pending().sub().b(), Some(&i));
 //!         let mut iter = field_set.into_iter();
 //!         for field_change in iter.clone() {
 //!             model.apply(field_change);
@@ -70,7 +175,6 @@
 
 #![no_std]
 #![allow(dead_code)]
-#![cfg_attr(test, feature(impl_trait_in_assoc_type))]
 
 #[doc(hidden)]
 pub mod bitset;
@@ -78,7 +182,43 @@ pub mod bitset;
 #[doc(hidden)]
 pub use bitset::{BitSet, BitSetOffsetted};
 
-use core::marker::PhantomData;
+mod codec;
+
+pub use codec::{CodecError, FieldCodec};
+
+#[doc(hidden)]
+pub use codec::{decode_tag, decode_value, encode_tag, encode_value, FieldTypeCodec};
+
+mod dispatch;
+
+pub use dispatch::{
+    Dispatcher, DispatcherFull, FieldHandler, FieldMask, FieldPath, FieldSelector,
+    MaskSubscription, Subscription, Then,
+};
+
+mod log;
+
+pub use log::{LogFieldSet, LogFieldSetIter, LogFull, LogOverflow};
+
+mod queue;
+
+pub use queue::{ChangeQueue, Consumer, Producer};
+
+#[cfg(feature = "critical-section")]
+mod shared;
+
+#[cfg(feature = "critical-section")]
+pub use shared::{SharedBatch, SharedFieldSet};
+
+#[cfg(feature = "async")]
+mod watch;
+
+#[cfg(feature = "async")]
+pub use watch::{Next, Receiver, Watch};
+
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub mod serde_support;
 
 pub use fieldset_macro::FieldSet;
 
@@ -86,237 +226,2906 @@ pub trait FieldSetter<T> {
     fn set(&mut self, value: T);
 }
 
-#[doc(hidden)]
-pub struct RawFieldSetter<'a, T>(pub &'a mut T);
+/// Identifier of a field of the flattened `0..VARIANCE` layout, such as the derived `FieldId`.
+pub trait FieldIndex: Copy + Eq + core::hash::Hash + core::fmt::Debug {
+    /// Position of the field in the flattened `0..VARIANCE` layout.
+    fn index(self) -> usize;
+}
 
-impl<'a, T> FieldSetter<T> for RawFieldSetter<'a, T> {
-    fn set(&mut self, value: T) {
-        *self.0 = value;
-    }
+/// Links a struct deriving `FieldSet` to its derived items.
+///
+/// This is what allows `#[fieldset]` fields to refer to their nested model through any path.
+pub trait FieldSetModel: Sized {
+    type FieldType;
+    type FieldId: FieldIndex;
+    type OptFieldSet: Default
+        + From<Self>
+        + FieldSink<Self::FieldType>
+        + IntoIterator<Item = Self::FieldType>;
+
+    /// Number of tracked fields, including the fields of nested models.
+    const VARIANCE: usize;
+
+    /// Metadata of the tracked fields, in the flattened `0..VARIANCE` layout.
+    const FIELDS: &'static [FieldInfo];
+
+    /// Position of the modified field in the flattened `0..VARIANCE` layout.
+    fn field_index(field: &Self::FieldType) -> usize;
+
+    /// Fields of the flattened layout whose pending modifications are made obsolete by `field`.
+    ///
+    /// This is empty except for enum variant switches, which supersede the fields of the enum.
+    fn superseded_fields(field: &Self::FieldType) -> core::ops::Range<usize>;
 }
 
-#[doc(hidden)]
-pub struct OptFieldSetter<'a, T>(pub &'a mut Option<T>);
+/// Models whose modifications can be computed by comparing two of their instances.
+///
+/// Implemented by the derived `diff` of models whose fields can be compared and cloned.
+pub trait FieldSetDiff: FieldSetModel {
+    /// Puts into `setter` the modifications that turn `old` into `new`.
+    fn diff<S: FieldSink<Self::FieldType> + ?Sized>(old: &Self, new: &Self, setter: &mut S);
+}
+
+/// Models that can tell whether a modification would leave them unchanged.
+///
+/// Implemented for models whose fields can be compared, which backs `DedupFieldSetter`.
+pub trait FieldSetDedup: FieldSetModel {
+    /// Whether applying `field` would leave the model unchanged.
+    ///
+    /// `is_pending` tells whether the field at a flattened index has a pending modification that
+    /// would be applied before `field`, such as a switch of the enclosing enum variant.
+    fn is_unchanged(&self, field: &Self::FieldType, is_pending: &dyn Fn(usize) -> bool) -> bool;
+}
+
+/// Models that can read the effective value of their fields, which backs `LayeredFieldSetter`.
+///
+/// Implemented for models whose fields can be cloned.
+pub trait FieldSetRead: FieldSetModel {
+    /// Value of the field at the flattened `index` once the modifications found through `pending`
+    /// are applied, or `None` for the fields of an inactive enum variant.
+    fn read(
+        &self,
+        index: usize,
+        pending: &dyn Fn(usize) -> Option<Self::FieldType>,
+    ) -> Option<Self::FieldType>;
+}
+
+/// Models that can apply modifications while keeping what they replaced, for undo and rollback.
+pub trait FieldSetUndo: FieldSetModel {
+    /// Applies `field` and returns the modification that restores the previous value, or `None`
+    /// if it did not apply, such as for the fields of an inactive enum variant.
+    fn apply_with_undo(&mut self, field: Self::FieldType) -> Option<Self::FieldType>;
 
-impl<'a, T> FieldSetter<T> for OptFieldSetter<'a, T> {
-    fn set(&mut self, value: T) {
-        *self.0 = Some(value);
+    /// Applies all `fields` while collecting into `inverse` the modifications that restore the
+    /// model as it was before the batch, in any order.
+    ///
+    /// `inverse` is usually an empty `BitFieldSet` or `PerfFieldSet`. Only the first previous value
+    /// of each field is kept, and a variant switch restores the whole enum as it was.
+    fn apply_with_inverse<S>(
+        &mut self,
+        fields: impl IntoIterator<Item = Self::FieldType>,
+        inverse: &mut S,
+    ) where
+        Self::FieldType: Clone,
+        S: FieldSink<Self::FieldType> + FieldSource<Self::FieldType> + ?Sized,
+    {
+        for field in fields {
+            let index = Self::field_index(&field);
+            // The enum is restored before it switches, so that the inverse switch restores it as
+            // it was before the batch.
+            for superseded in Self::superseded_fields(&field) {
+                if let Some(previous) = inverse.lookup(superseded) {
+                    self.apply_with_undo(previous.clone());
+                }
+            }
+            let Some(previous) = self.apply_with_undo(field) else {
+                continue;
+            };
+            let is_restored = inverse.lookup(index).is_some()
+                || (0..index).any(|switch| {
+                    inverse
+                        .lookup(switch)
+                        .is_some_and(|previous| Self::superseded_fields(previous).contains(&index))
+                });
+            if !is_restored {
+                inverse.put(index, previous);
+            }
+        }
     }
 }
 
-#[doc(hidden)]
-pub struct BitFieldLeafSetter<'a, V, T, F>(
-    pub BitSetOffsetted<'a>,
-    pub &'a mut [T],
-    pub &'a mut usize,
-    pub usize,
-    pub F,
-    pub PhantomData<V>,
-);
+/// Models whose modifications are checked by the validators given with
+/// `#[fieldset(validate = path)]`, for transactional application.
+pub trait FieldSetValidate: FieldSetUndo {
+    /// Checks the value of `field` with the validator of its field, if any.
+    fn validate_field(field: &Self::FieldType) -> Result<(), Self::FieldId>;
 
-#[doc(hidden)]
-pub struct BitFieldSetter<'a, T, F>(
-    pub BitSetOffsetted<'a>,
-    pub &'a mut [T],
-    pub &'a mut usize,
-    pub F,
-);
+    /// Checks the model with the validators of its nested models and then its own, if any.
+    fn validate(&self) -> Result<(), Self::FieldId>;
 
-impl<'a, V, T, F: Fn(V) -> T> FieldSetter<V> for BitFieldLeafSetter<'a, V, T, F> {
-    fn set(&mut self, value: V) {
-        if !self.0.test(self.3) {
-            self.0.set(self.3);
-            self.1[*self.2] = self.4(value);
-            *self.2 += 1;
+    /// Applies all `fields` if each of them and the resulting model are valid, and otherwise
+    /// leaves the model untouched and returns the field that failed validation.
+    ///
+    /// `inverse` must be an empty `BitFieldSet` or `PerfFieldSet`, which holds the rollback.
+    fn try_apply_all_with<S>(
+        &mut self,
+        fields: impl IntoIterator<Item = Self::FieldType>,
+        mut inverse: S,
+    ) -> Result<(), Self::FieldId>
+    where
+        Self::FieldType: Clone,
+        S: FieldSink<Self::FieldType>
+            + FieldSource<Self::FieldType>
+            + IntoIterator<Item = Self::FieldType>,
+    {
+        let mut result = Ok(());
+        for field in fields {
+            result = Self::validate_field(&field);
+            if result.is_err() {
+                break;
+            }
+            self.apply_with_inverse(Some(field), &mut inverse);
+        }
+        let result = result.and_then(|()| self.validate());
+        if result.is_err() {
+            for field in inverse {
+                self.apply_with_undo(field);
+            }
         }
+        result
     }
 }
 
+/// Metadata of a tracked field, as listed in the derived `FIELDS` tables.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FieldInfo {
+    /// Position of the field in the flattened `0..VARIANCE` layout.
+    pub index: usize,
+    /// Name of the field within its own model.
+    pub name: &'static str,
+    /// Dotted path of the field from the root model, such as `sub.b`.
+    pub path: &'static str,
+}
+
 #[doc(hidden)]
-pub struct PerfFieldLeafSetter<'a, V, T, F>(
-    pub &'a mut [u16],
-    pub &'a mut [T],
-    pub &'a mut usize,
-    pub usize,
-    pub F,
-    pub PhantomData<V>,
-);
+pub const fn nested_paths_len(prefix: &str, fields: &[FieldInfo]) -> usize {
+    let mut len = 0;
+    let mut i = 0;
+    while i < fields.len() {
+        len += prefix.len() + 1 + fields[i].path.len();
+        i += 1;
+    }
+    len
+}
 
+/// Compile-time builder of the derived `FIELDS` tables.
+///
+/// The dotted paths are concatenated into a single buffer that must be stored in a constant
+/// before `fields` can borrow it for the `'static` lifetime.
 #[doc(hidden)]
-pub struct PerfFieldSetter<'a, T, F>(pub &'a mut [u16], pub &'a mut [T], pub &'a mut usize, pub F);
+pub struct FieldTable<const V: usize, const L: usize> {
+    names: [&'static str; V],
+    ends: [usize; V],
+    pub paths: [u8; L],
+    len: usize,
+}
 
-impl<'a, V, T, F: Fn(V) -> T> FieldSetter<V> for PerfFieldLeafSetter<'a, V, T, F> {
-    fn set(&mut self, value: V) {
-        if self.0[self.3] == 0 {
-            self.0[self.3] = *self.2 as u16 + 1;
-            self.1[*self.2] = self.4(value);
-            *self.2 += 1;
-        } else {
-            self.1[self.0[self.3] as usize - 1] = self.4(value);
+impl<const V: usize, const L: usize> Default for FieldTable<V, L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const V: usize, const L: usize> FieldTable<V, L> {
+    pub const fn new() -> Self {
+        Self {
+            names: [""; V],
+            ends: [0; V],
+            paths: [0; L],
+            len: 0,
+        }
+    }
+
+    const fn push(mut self, name: &'static str, segments: [&str; 3]) -> Self {
+        let mut end = match self.len {
+            0 => 0,
+            len => self.ends[len - 1],
+        };
+        let mut s = 0;
+        while s < segments.len() {
+            let bytes = segments[s].as_bytes();
+            let mut i = 0;
+            while i < bytes.len() {
+                self.paths[end] = bytes[i];
+                end += 1;
+                i += 1;
+            }
+            s += 1;
         }
+        self.names[self.len] = name;
+        self.ends[self.len] = end;
+        self.len += 1;
+        self
+    }
+
+    pub const fn leaf(self, name: &'static str) -> Self {
+        self.push(name, [name, "", ""])
+    }
+
+    pub const fn nested(mut self, prefix: &'static str, fields: &[FieldInfo]) -> Self {
+        let mut i = 0;
+        while i < fields.len() {
+            self = self.push(fields[i].name, [prefix, ".", fields[i].path]);
+            i += 1;
+        }
+        self
+    }
+
+    pub const fn fields(&self, paths: &'static [u8; L]) -> [FieldInfo; V] {
+        let mut fields = [FieldInfo {
+            index: 0,
+            name: "",
+            path: "",
+        }; V];
+        let mut start = 0;
+        let mut i = 0;
+        while i < V {
+            let (path, _) = paths.split_at(self.ends[i]);
+            let (_, path) = path.split_at(start);
+            fields[i] = FieldInfo {
+                index: i,
+                name: self.names[i],
+                path: match core::str::from_utf8(path) {
+                    Ok(path) => path,
+                    Err(_) => panic!("field paths are built from valid identifiers"),
+                },
+            };
+            start = self.ends[i];
+            i += 1;
+        }
+        fields
     }
 }
 
-#[cfg(test)]
-mod test {
-    extern crate self as fieldset;
-    use super::*;
+/// Receiver of `FieldType` modifications that backs the derived `FieldSetter` traits.
+///
+/// `index` is the position of the modified field in the flattened `0..VARIANCE` layout.
+pub trait FieldSink<T> {
+    fn put(&mut self, index: usize, value: T);
+}
 
-    #[derive(Clone, Copy, FieldSet)]
-    struct Inner3 {
-        field_7: f32,
-        field_8: u32,
-        #[fieldset_skip]
-        field_skipped: f32,
+impl<T, S: FieldSink<T> + ?Sized> FieldSink<T> for &mut S {
+    fn put(&mut self, index: usize, value: T) {
+        (**self).put(index, value)
     }
+}
 
-    #[derive(Clone, Copy, FieldSet)]
-    struct Inner2 {
-        field_5: f32,
-        field_6: u32,
+pub struct LeafFieldSetter<'a, S: ?Sized, T, V> {
+    sink: &'a mut S,
+    index: usize,
+    wrap: fn(V) -> T,
+    unwrap: fn(T) -> Option<V>,
+}
+
+impl<'a, S: ?Sized, T, V> LeafFieldSetter<'a, S, T, V> {
+    #[doc(hidden)]
+    pub fn new(
+        sink: &'a mut S,
+        index: usize,
+        wrap: fn(V) -> T,
+        unwrap: fn(T) -> Option<V>,
+    ) -> Self {
+        Self {
+            sink,
+            index,
+            wrap,
+            unwrap,
+        }
     }
+}
 
-    #[derive(Clone, Copy, FieldSet)]
-    struct Inner {
-        field_3: f32,
-        field_4: u32,
-        #[fieldset]
-        field_i2: Inner2,
-        #[fieldset]
-        field_i3: Inner3,
+impl<'a, S: FieldSink<T> + ?Sized, T, V> FieldSetter<V> for LeafFieldSetter<'a, S, T, V> {
+    fn set(&mut self, value: V) {
+        self.sink.put(self.index, (self.wrap)(value));
     }
+}
 
-    #[derive(Clone, Copy, FieldSet)]
-    struct Outer {
-        field_1: f32,
-        field_2: u32,
-        #[fieldset]
-        field_i: Inner,
+impl<'a, S: FieldRead<T> + ?Sized, T, V> LeafFieldSetter<'a, S, T, V> {
+    /// Effective value of the field: the pending modification if any, else the current value.
+    ///
+    /// This is `None` for the fields of an inactive enum variant.
+    pub fn get(&self) -> Option<V> {
+        self.sink.read(self.index).and_then(self.unwrap)
     }
 
-    #[derive(Clone, Copy, FieldSet)]
-    struct TestFirstField {
-        #[fieldset]
-        field: Inner,
+    /// Sets the field to `f` of its effective value, if any.
+    pub fn update(&mut self, f: impl FnOnce(V) -> V)
+    where
+        S: FieldSink<T>,
+    {
+        if let Some(value) = self.get() {
+            self.set(f(value));
+        }
     }
+}
 
-    #[test]
-    pub fn opt_field_set_full_check() {
-        let mut fieldset = OuterOptFieldSet::new();
-        let e1 = OuterFieldType::Field1(1.0);
-        let e2 = OuterFieldType::Field2(2);
-        let e3 = OuterFieldType::FieldI(InnerFieldType::Field3(3.0));
-        let e4 = OuterFieldType::FieldI(InnerFieldType::Field4(4));
-        let e5 = OuterFieldType::FieldI(InnerFieldType::FieldI2(Inner2FieldType::Field5(5.0)));
-        let e6 = OuterFieldType::FieldI(InnerFieldType::FieldI2(Inner2FieldType::Field6(6)));
-        let e7 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field7(7.0)));
-        let e8 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field8(8)));
+pub struct NestedFieldSetter<'a, S: ?Sized, T, U> {
+    sink: &'a mut S,
+    offset: usize,
+    wrap: fn(U) -> T,
+    unwrap: fn(T) -> Option<U>,
+}
 
-        let e4_2 = OuterFieldType::FieldI(InnerFieldType::Field4(42));
-        let e7_2 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field7(7.2)));
+impl<'a, S: ?Sized, T, U> NestedFieldSetter<'a, S, T, U> {
+    #[doc(hidden)]
+    pub fn new(
+        sink: &'a mut S,
+        offset: usize,
+        wrap: fn(U) -> T,
+        unwrap: fn(T) -> Option<U>,
+    ) -> Self {
+        Self {
+            sink,
+            offset,
+            wrap,
+            unwrap,
+        }
+    }
+}
 
-        fieldset.apply(e1);
-        fieldset.apply(e2);
-        fieldset.apply(e3);
-        fieldset.apply(e4);
-        fieldset.apply(e5);
-        fieldset.apply(e6);
-        fieldset.apply(e7);
-        fieldset.apply(e8);
+impl<'a, S: FieldSink<T> + ?Sized, T, U> FieldSink<U> for NestedFieldSetter<'a, S, T, U> {
+    fn put(&mut self, index: usize, value: U) {
+        self.sink.put(self.offset + index, (self.wrap)(value));
+    }
+}
 
-        fieldset.apply(e4_2);
-        fieldset.apply(e7_2);
+impl<'a, S: FieldRead<T> + ?Sized, T, U> FieldRead<U> for NestedFieldSetter<'a, S, T, U> {
+    fn read(&self, index: usize) -> Option<U> {
+        self.sink.read(self.offset + index).and_then(self.unwrap)
+    }
+}
 
-        let mut iter = fieldset.into_iter();
+/// Setter adapter over the current `model` and its pending modifications, whose setters provide
+/// `get` and `update` on the effective value of the fields.
+pub struct LayeredFieldSetter<'a, M, S: ?Sized> {
+    model: &'a M,
+    sink: &'a mut S,
+}
 
-        assert_eq!(iter.next(), Some(e1));
-        assert_eq!(iter.next(), Some(e2));
-        assert_eq!(iter.next(), Some(e3));
-        assert_eq!(iter.next(), Some(e4_2));
-        assert_eq!(iter.next(), Some(e5));
-        assert_eq!(iter.next(), Some(e6));
-        assert_eq!(iter.next(), Some(e7_2));
-        assert_eq!(iter.next(), Some(e8));
-        assert_eq!(iter.next(), None);
+impl<'a, M, S: ?Sized> LayeredFieldSetter<'a, M, S> {
+    pub fn new(model: &'a M, sink: &'a mut S) -> Self {
+        Self { model, sink }
     }
+}
 
-    #[test]
-    pub fn bit_field_set_full_check() {
-        let mut fieldset = OuterBitFieldSet::new();
-        let e1 = OuterFieldType::Field1(1.0);
-        let e2 = OuterFieldType::Field2(2);
-        let e3 = OuterFieldType::FieldI(InnerFieldType::Field3(3.0));
-        let e4 = OuterFieldType::FieldI(InnerFieldType::Field4(4));
-        let e5 = OuterFieldType::FieldI(InnerFieldType::FieldI2(Inner2FieldType::Field5(5.0)));
-        let e6 = OuterFieldType::FieldI(InnerFieldType::FieldI2(Inner2FieldType::Field6(6)));
-        let e7 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field7(7.0)));
-        let e8 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field8(8)));
+impl<'a, M: FieldSetModel, S: FieldSink<M::FieldType> + ?Sized> FieldSink<M::FieldType>
+    for LayeredFieldSetter<'a, M, S>
+{
+    fn put(&mut self, index: usize, value: M::FieldType) {
+        self.sink.put(index, value);
+    }
+}
 
-        let e4_2 = OuterFieldType::FieldI(InnerFieldType::Field4(42));
-        let e7_2 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field7(7.2)));
+impl<'a, M, S> FieldRead<M::FieldType> for LayeredFieldSetter<'a, M, S>
+where
+    M: FieldSetRead,
+    M::FieldType: Clone,
+    S: FieldSource<M::FieldType> + ?Sized,
+{
+    fn read(&self, index: usize) -> Option<M::FieldType> {
+        self.model
+            .read(index, &|index| self.sink.lookup(index).cloned())
+    }
+}
 
-        fieldset.apply(e1);
-        fieldset.apply(e2);
-        fieldset.apply(e3);
-        fieldset.apply(e4);
-        fieldset.apply(e5);
-        fieldset.apply(e6);
-        fieldset.apply(e7);
-        fieldset.apply(e8);
+/// Setter adapter that only records the modifications that change the current `model`.
+///
+/// Writes to fields with a pending modification are always recorded, so that they replace it.
+pub struct DedupFieldSetter<'a, M, S: ?Sized> {
+    model: &'a M,
+    sink: &'a mut S,
+}
 
-        fieldset.apply(e4_2); // ignored!
-        fieldset.apply(e7_2); // ignored!
+impl<'a, M, S: ?Sized> DedupFieldSetter<'a, M, S> {
+    pub fn new(model: &'a M, sink: &'a mut S) -> Self {
+        Self { model, sink }
+    }
+}
 
-        let mut iter = fieldset.into_iter();
+impl<'a, M, S> FieldSink<M::FieldType> for DedupFieldSetter<'a, M, S>
+where
+    M: FieldSetDedup,
+    S: FieldSink<M::FieldType> + FieldSource<M::FieldType> + ?Sized,
+{
+    fn put(&mut self, index: usize, value: M::FieldType) {
+        let is_pending = |index| self.sink.lookup(index).is_some();
+        if is_pending(index) || !self.model.is_unchanged(&value, &is_pending) {
+            self.sink.put(index, value);
+        }
+    }
+}
 
-        assert_eq!(iter.next(), Some(e1));
-        assert_eq!(iter.next(), Some(e2));
-        assert_eq!(iter.next(), Some(e3));
-        assert_eq!(iter.next(), Some(e4));
-        assert_eq!(iter.next(), Some(e5));
-        assert_eq!(iter.next(), Some(e6));
-        assert_eq!(iter.next(), Some(e7));
-        assert_eq!(iter.next(), Some(e8));
-        assert_eq!(iter.next(), None);
+impl<'a, M, S> FieldRead<M::FieldType> for DedupFieldSetter<'a, M, S>
+where
+    M: FieldSetRead,
+    M::FieldType: Clone,
+    S: FieldSource<M::FieldType> + ?Sized,
+{
+    fn read(&self, index: usize) -> Option<M::FieldType> {
+        self.model
+            .read(index, &|index| self.sink.lookup(index).cloned())
     }
+}
 
-    #[test]
-    pub fn perf_field_set_full_check() {
-        let mut fieldset = OuterPerfFieldSet::new();
-        let e1 = OuterFieldType::Field1(1.0);
-        let e2 = OuterFieldType::Field2(2);
-        let e3 = OuterFieldType::FieldI(InnerFieldType::Field3(3.0));
-        let e4 = OuterFieldType::FieldI(InnerFieldType::Field4(4));
-        let e5 = OuterFieldType::FieldI(InnerFieldType::FieldI2(Inner2FieldType::Field5(5.0)));
-        let e6 = OuterFieldType::FieldI(InnerFieldType::FieldI2(Inner2FieldType::Field6(6)));
-        let e7 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field7(7.0)));
-        let e8 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field8(8)));
+#[doc(hidden)]
+pub struct BitFieldSetter<'a, T>(
+    pub BitSetOffsetted<'a>,
+    pub &'a mut [Option<T>],
+    pub &'a mut usize,
+);
 
-        let e4_2 = OuterFieldType::FieldI(InnerFieldType::Field4(42));
-        let e7_2 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field7(7.2)));
+impl<'a, T> BitFieldSetter<'a, T> {
+    /// Discards the pending modifications of the fields in `indices` before the field at `index`
+    /// is put, unless it is already modified, since its new modification is then ignored.
+    pub fn discard(
+        &mut self,
+        index: usize,
+        indices: core::ops::Range<usize>,
+        field_index: fn(&T) -> usize,
+    ) {
+        if indices.is_empty() || self.0.test(index) {
+            return;
+        }
+        for index in indices.clone() {
+            self.0.clear(index);
+        }
+        let mut len = 0;
+        for position in 0..*self.2 {
+            let is_kept = self.1[position]
+                .as_ref()
+                .is_some_and(|field| !indices.contains(&field_index(field)));
+            if is_kept {
+                self.1.swap(len, position);
+                len += 1;
+            } else {
+                self.1[position] = None;
+            }
+        }
+        *self.2 = len;
+    }
+}
 
-        fieldset.apply(e1);
-        fieldset.apply(e2);
-        fieldset.apply(e3);
-        fieldset.apply(e4);
-        fieldset.apply(e5);
-        fieldset.apply(e6);
-        fieldset.apply(e7);
-        fieldset.apply(e8);
+impl<'a, T> FieldSink<T> for BitFieldSetter<'a, T> {
+    fn put(&mut self, index: usize, value: T) {
+        if !self.0.test(index) {
+            self.0.set(index);
+            self.1[*self.2] = Some(value);
+            *self.2 += 1;
+        }
+    }
+}
 
-        fieldset.apply(e4_2);
-        fieldset.apply(e7_2);
+/// Integer type of the positions tracked by a `PerfFieldSet`, one per field.
+///
+/// It is the narrowest of `u8`, `u16` and `u32` that fits the number of fields, unless selected
+/// with `#[fieldset(perf_index = u8)]`.
+pub trait PerfIndex: Copy {
+    /// Largest position, and therefore number of fields, that can be tracked.
+    const MAX: usize;
 
-        let mut iter = fieldset.into_iter();
+    #[doc(hidden)]
+    const ZERO: Self;
 
-        assert_eq!(iter.next(), Some(e1));
-        assert_eq!(iter.next(), Some(e2));
+    #[doc(hidden)]
+    fn from_position(position: usize) -> Self;
+
+    #[doc(hidden)]
+    fn to_position(self) -> usize;
+}
+
+macro_rules! impl_perf_index {
+    ($($ty:ty),*) => {
+        $(
+            impl PerfIndex for $ty {
+                const MAX: usize = <$ty>::MAX as usize;
+
+                const ZERO: Self = 0;
+
+                fn from_position(position: usize) -> Self {
+                    position as $ty
+                }
+
+                fn to_position(self) -> usize {
+                    self as usize
+                }
+            }
+        )*
+    };
+}
+
+impl_perf_index!(u8, u16, u32);
+
+/// Selects the `PerfIndex` of `BITS` bits, as computed by `perf_index_bits`.
+#[doc(hidden)]
+pub struct PerfIndexBits<const BITS: usize>;
+
+#[doc(hidden)]
+pub trait SelectPerfIndex {
+    type Index: PerfIndex;
+}
+
+impl SelectPerfIndex for PerfIndexBits<8> {
+    type Index = u8;
+}
+
+impl SelectPerfIndex for PerfIndexBits<16> {
+    type Index = u16;
+}
+
+impl SelectPerfIndex for PerfIndexBits<32> {
+    type Index = u32;
+}
+
+#[doc(hidden)]
+pub const fn perf_index_bits(variance: usize) -> usize {
+    if variance <= u8::MAX as usize {
+        8
+    } else if variance <= u16::MAX as usize {
+        16
+    } else {
+        32
+    }
+}
+
+#[doc(hidden)]
+pub struct PerfFieldSetter<'a, T, I>(pub &'a mut [I], pub &'a mut [Option<T>], pub &'a mut usize);
+
+impl<'a, T, I: PerfIndex> PerfFieldSetter<'a, T, I> {
+    /// Discards the pending modifications of the fields in `indices`, compacting the remaining
+    /// ones in a single pass.
+    pub fn discard(&mut self, indices: core::ops::Range<usize>, field_index: fn(&T) -> usize) {
+        let mut is_pending = false;
+        for index in indices.clone() {
+            is_pending |= self.0[index].to_position() != 0;
+            self.0[index] = I::ZERO;
+        }
+        if !is_pending {
+            return;
+        }
+        let mut len = 0;
+        for position in 0..*self.2 {
+            let kept = self.1[position]
+                .as_ref()
+                .map(field_index)
+                .filter(|index| !indices.contains(index));
+            match kept {
+                Some(index) => {
+                    self.1.swap(len, position);
+                    len += 1;
+                    self.0[index] = I::from_position(len);
+                }
+                None => self.1[position] = None,
+            }
+        }
+        *self.2 = len;
+    }
+}
+
+impl<'a, T, I: PerfIndex> FieldSink<T> for PerfFieldSetter<'a, T, I> {
+    fn put(&mut self, index: usize, value: T) {
+        match self.0[index].to_position() {
+            0 => {
+                self.0[index] = I::from_position(*self.2 + 1);
+                self.1[*self.2] = Some(value);
+                *self.2 += 1;
+            }
+            position => self.1[position - 1] = Some(value),
+        }
+    }
+}
+
+#[doc(hidden)]
+pub struct IndexFieldSetter<'a, T>(
+    pub BitSetOffsetted<'a>,
+    pub &'a mut [Option<T>],
+    pub &'a mut usize,
+);
+
+impl<'a, T> IndexFieldSetter<'a, T> {
+    pub fn discard(&mut self, indices: core::ops::Range<usize>) {
+        for index in indices {
+            if self.0.test(index) {
+                self.0.clear(index);
+                self.1[index] = None;
+                *self.2 -= 1;
+            }
+        }
+    }
+}
+
+impl<'a, T> FieldSink<T> for IndexFieldSetter<'a, T> {
+    fn put(&mut self, index: usize, value: T) {
+        if !self.0.test(index) {
+            self.0.set(index);
+            *self.2 += 1;
+        }
+        self.1[index] = Some(value);
+    }
+}
+
+/// Read access to the effective value of the fields, which backs `get` and `update` on the
+/// setters of `LayeredFieldSetter`.
+///
+/// `index` is the position of the field in the flattened `0..VARIANCE` layout.
+pub trait FieldRead<T> {
+    fn read(&self, index: usize) -> Option<T>;
+}
+
+/// Lookup of pending `FieldType` modifications that backs the `Pending` views.
+///
+/// `index` is the position of the field in the flattened `0..VARIANCE` layout.
+pub trait FieldSource<T> {
+    fn lookup(&self, index: usize) -> Option<&T>;
+}
+
+/// Storage whose pending modifications can be discarded in place, implemented by the `clear` of
+/// the derived `FieldSet` types.
+pub trait FieldClear {
+    fn clear(&mut self);
+}
+
+#[doc(hidden)]
+pub trait FieldLookup<'a, T>: Copy {
+    fn lookup(self, index: usize) -> Option<&'a T>;
+}
+
+impl<'a, T, S: FieldSource<T> + ?Sized> FieldLookup<'a, T> for &'a S {
+    fn lookup(self, index: usize) -> Option<&'a T> {
+        FieldSource::lookup(self, index)
+    }
+}
+
+#[doc(hidden)]
+pub struct NestedLookup<P, T, U> {
+    parent: P,
+    offset: usize,
+    unwrap: fn(&T) -> Option<&U>,
+}
+
+impl<P: Copy, T, U> Clone for NestedLookup<P, T, U> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<P: Copy, T, U> Copy for NestedLookup<P, T, U> {}
+
+impl<'a, P: FieldLookup<'a, T>, T: 'a, U: 'a> FieldLookup<'a, U> for NestedLookup<P, T, U> {
+    fn lookup(self, index: usize) -> Option<&'a U> {
+        self.parent
+            .lookup(self.offset + index)
+            .and_then(self.unwrap)
+    }
+}
+
+/// Read access to the pending modifications of a storage of `M`, which the derived
+/// `FieldGetter` trait of `M` is implemented for.
+pub trait PendingView<'p, M: FieldSetModel> {
+    #[doc(hidden)]
+    type Nested<N: FieldSetModel<FieldType: 'p, OptFieldSet: 'p>>: PendingView<'p, N>;
+
+    /// Pending value of the field at `index`, found through `opt` in an `OptFieldSet` and through
+    /// `unwrap` in the other storages.
+    #[doc(hidden)]
+    fn get<V>(
+        &self,
+        index: usize,
+        opt: fn(&'p M::OptFieldSet) -> Option<&'p V>,
+        unwrap: fn(&M::FieldType) -> Option<&V>,
+    ) -> Option<&'p V>;
+
+    /// View of the nested model whose fields start at `offset`.
+    #[doc(hidden)]
+    fn nested<N: FieldSetModel<FieldType: 'p, OptFieldSet: 'p>>(
+        &self,
+        offset: usize,
+        opt: fn(&'p M::OptFieldSet) -> &'p N::OptFieldSet,
+        unwrap: fn(&M::FieldType) -> Option<&N::FieldType>,
+    ) -> Self::Nested<N>;
+}
+
+/// Read access to the pending modifications of a `BitFieldSet` or `PerfFieldSet` through the
+/// derived `FieldGetter` traits.
+#[derive(Clone, Copy, Debug)]
+pub struct Pending<P>(P);
+
+impl<P> Pending<P> {
+    #[doc(hidden)]
+    pub fn new(lookup: P) -> Self {
+        Self(lookup)
+    }
+
+    #[doc(hidden)]
+    pub fn get<'a, T>(this: &Self, index: usize) -> Option<&'a T>
+    where
+        P: FieldLookup<'a, T>,
+    {
+        this.0.lookup(index)
+    }
+
+    #[doc(hidden)]
+    pub fn nested<T, U>(
+        this: &Self,
+        offset: usize,
+        unwrap: fn(&T) -> Option<&U>,
+    ) -> Pending<NestedLookup<P, T, U>>
+    where
+        P: Copy,
+    {
+        Pending(NestedLookup {
+            parent: this.0,
+            offset,
+            unwrap,
+        })
+    }
+}
+
+impl<'p, M, P> PendingView<'p, M> for Pending<P>
+where
+    M: FieldSetModel<FieldType: 'p>,
+    P: FieldLookup<'p, M::FieldType>,
+{
+    type Nested<N: FieldSetModel<FieldType: 'p, OptFieldSet: 'p>> =
+        Pending<NestedLookup<P, M::FieldType, N::FieldType>>;
+
+    fn get<V>(
+        &self,
+        index: usize,
+        _: fn(&'p M::OptFieldSet) -> Option<&'p V>,
+        unwrap: fn(&M::FieldType) -> Option<&V>,
+    ) -> Option<&'p V> {
+        Pending::get(self, index).and_then(unwrap)
+    }
+
+    fn nested<N: FieldSetModel<FieldType: 'p, OptFieldSet: 'p>>(
+        &self,
+        offset: usize,
+        _: fn(&'p M::OptFieldSet) -> &'p N::OptFieldSet,
+        unwrap: fn(&M::FieldType) -> Option<&N::FieldType>,
+    ) -> Self::Nested<N> {
+        Pending::nested(self, offset, unwrap)
+    }
+}
+
+/// Read access to the pending modifications of an `OptFieldSet` through the derived
+/// `FieldGetter` traits.
+pub struct OptPending<'p, M: FieldSetModel>(&'p M::OptFieldSet);
+
+impl<'p, M: FieldSetModel> OptPending<'p, M> {
+    #[doc(hidden)]
+    pub fn new(fieldset: &'p M::OptFieldSet) -> Self {
+        Self(fieldset)
+    }
+}
+
+impl<'p, M: FieldSetModel> Clone for OptPending<'p, M> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'p, M: FieldSetModel> Copy for OptPending<'p, M> {}
+
+impl<'p, M: FieldSetModel> PendingView<'p, M> for OptPending<'p, M> {
+    type Nested<N: FieldSetModel<FieldType: 'p, OptFieldSet: 'p>> = OptPending<'p, N>;
+
+    fn get<V>(
+        &self,
+        _: usize,
+        opt: fn(&'p M::OptFieldSet) -> Option<&'p V>,
+        _: fn(&M::FieldType) -> Option<&V>,
+    ) -> Option<&'p V> {
+        opt(self.0)
+    }
+
+    fn nested<N: FieldSetModel<FieldType: 'p, OptFieldSet: 'p>>(
+        &self,
+        _: usize,
+        opt: fn(&'p M::OptFieldSet) -> &'p N::OptFieldSet,
+        _: fn(&M::FieldType) -> Option<&N::FieldType>,
+    ) -> OptPending<'p, N> {
+        OptPending(opt(self.0))
+    }
+}
+
+/// Iterator over the modifications of a `BitFieldSet`, `PerfFieldSet` or `LogFieldSet`, borrowing
+/// the set.
+#[derive(Debug)]
+pub struct Iter<'a, T>(core::slice::Iter<'a, Option<T>>);
+
+impl<'a, T> Iter<'a, T> {
+    #[doc(hidden)]
+    pub fn new(fields: &'a [Option<T>]) -> Self {
+        Self(fields.iter())
+    }
+}
+
+impl<'a, T> Clone for Iter<'a, T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().and_then(Option::as_ref)
+    }
+}
+
+/// Iterator over the modifications of a `BitFieldSet`, `PerfFieldSet` or `LogFieldSet` that were
+/// drained.
+///
+/// The set is reset as soon as it is drained. Modifications that are not yielded are dropped along
+/// with the iterator.
+#[derive(Debug)]
+pub struct Drain<'a, T>(core::slice::IterMut<'a, Option<T>>);
+
+impl<'a, T> Drain<'a, T> {
+    #[doc(hidden)]
+    pub fn new(fields: &'a mut [Option<T>]) -> Self {
+        Self(fields.iter_mut())
+    }
+}
+
+impl<'a, T> Iterator for Drain<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().and_then(Option::take)
+    }
+}
+
+impl<'a, T> Drop for Drain<'a, T> {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
+
+/// Iterator over the modifications of an `IndexFieldSet`, borrowing the set.
+#[derive(Debug)]
+pub struct IndexIter<'a, T, const N_32: usize> {
+    bitset: &'a BitSet<N_32>,
+    fields: &'a [Option<T>],
+    index: usize,
+}
+
+impl<'a, T, const N_32: usize> IndexIter<'a, T, N_32> {
+    #[doc(hidden)]
+    pub fn new(bitset: &'a BitSet<N_32>, fields: &'a [Option<T>]) -> Self {
+        Self {
+            bitset,
+            fields,
+            index: 0,
+        }
+    }
+}
+
+impl<'a, T, const N_32: usize> Clone for IndexIter<'a, T, N_32> {
+    fn clone(&self) -> Self {
+        Self { ..*self }
+    }
+}
+
+impl<'a, T, const N_32: usize> Iterator for IndexIter<'a, T, N_32> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.bitset.next_set(self.index)?;
+        self.index = index + 1;
+        self.fields[index].as_ref()
+    }
+}
+
+/// Iterator over the modifications of an `IndexFieldSet` that were drained.
+///
+/// The set is reset as soon as it is drained. Modifications that are not yielded are dropped along
+/// with the iterator.
+#[derive(Debug)]
+pub struct IndexDrain<'a, T, const N_32: usize> {
+    bitset: BitSet<N_32>,
+    fields: &'a mut [Option<T>],
+    index: usize,
+}
+
+impl<'a, T, const N_32: usize> IndexDrain<'a, T, N_32> {
+    #[doc(hidden)]
+    pub fn new(bitset: BitSet<N_32>, fields: &'a mut [Option<T>]) -> Self {
+        Self {
+            bitset,
+            fields,
+            index: 0,
+        }
+    }
+}
+
+impl<'a, T, const N_32: usize> Iterator for IndexDrain<'a, T, N_32> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.bitset.next_set(self.index)?;
+        self.index = index + 1;
+        self.fields[index].take()
+    }
+}
+
+impl<'a, T, const N_32: usize> Drop for IndexDrain<'a, T, N_32> {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate self as fieldset;
+    use super::*;
+
+    // The crate is `no_std`, so iterators are collected into a fixed-size array.
+    #[derive(Debug)]
+    struct Vec3<T>([Option<T>; 3]);
+
+    impl<T> FromIterator<T> for Vec3<T> {
+        fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+            let mut iter = iter.into_iter();
+            let res = Self([(); 3].map(|_| iter.next()));
+            assert!(iter.next().is_none());
+            res
+        }
+    }
+
+    impl<T: PartialEq> PartialEq<[Option<T>; 3]> for Vec3<T> {
+        fn eq(&self, other: &[Option<T>; 3]) -> bool {
+            self.0 == *other
+        }
+    }
+
+    // Runs the same modifications against every storage of a test.
+    fn for_each_fieldset<T, const N: usize>(
+        fieldsets: [&mut dyn FieldSink<T>; N],
+        mut f: impl FnMut(&mut dyn FieldSink<T>),
+    ) {
+        for fieldset in fieldsets {
+            f(fieldset);
+        }
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    struct Inner3 {
+        field_7: f32,
+        field_8: u32,
+        #[fieldset_skip]
+        field_skipped: f32,
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    struct Inner2 {
+        field_5: f32,
+        field_6: u32,
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    struct Inner {
+        field_3: f32,
+        field_4: u32,
+        #[fieldset]
+        field_i2: Inner2,
+        #[fieldset]
+        field_i3: Inner3,
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    struct Outer {
+        field_1: f32,
+        field_2: u32,
+        #[fieldset]
+        field_i: Inner,
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    struct TestFirstField {
+        #[fieldset]
+        field: Inner,
+    }
+
+    mod motor {
+        use super::*;
+
+        #[derive(Clone, Copy, FieldSet)]
+        pub struct MotorModel {
+            pub speed: u32,
+            pub enabled: bool,
+        }
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    struct Wrapper<T: Copy> {
+        value: T,
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    struct PathOuter {
+        #[fieldset]
+        motor: self::motor::MotorModel,
+        #[fieldset]
+        wrapper: Wrapper<u8>,
+        field: f32,
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    struct GenericInner<T: Copy, const N: usize> {
+        field_a: [T; N],
+        field_b: T,
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    struct GenericOuter<'a, T, const N: usize>
+    where
+        T: Copy,
+    {
+        field_c: &'a str,
+        #[fieldset]
+        field_i: GenericInner<T, N>,
+    }
+
+    mod gyro {
+        use super::*;
+
+        #[derive(Clone, Copy, FieldSet)]
+        pub struct Axis<T: Copy> {
+            pub rate: T,
+            pub valid: bool,
+        }
+    }
+
+    use gyro::Axis as GyroAxis;
+
+    #[derive(Clone, Copy, FieldSet)]
+    struct AliasOuter<T: Copy> {
+        #[fieldset]
+        axis: GyroAxis<T>,
+        count: u32,
+    }
+
+    trait Num: Copy + Into<f64> {}
+
+    impl Num for u8 {}
+    impl Num for f32 {}
+
+    #[derive(Clone, Copy, FieldSet)]
+    struct Reading<T: Num> {
+        value: T,
+        valid: bool,
+    }
+
+    // `()` does not implement `Num`, so the layout is reached through an instance that does.
+    #[derive(Clone, Copy, FieldSet)]
+    struct Station<T: Num> {
+        id: u8,
+        #[fieldset(layout = Reading<f32>)]
+        reading: Reading<T>,
+    }
+
+    #[test]
+    pub fn opt_field_set_full_check() {
+        let mut fieldset = OuterOptFieldSet::new();
+        let e1 = OuterFieldType::Field1(1.0);
+        let e2 = OuterFieldType::Field2(2);
+        let e3 = OuterFieldType::FieldI(InnerFieldType::Field3(3.0));
+        let e4 = OuterFieldType::FieldI(InnerFieldType::Field4(4));
+        let e5 = OuterFieldType::FieldI(InnerFieldType::FieldI2(Inner2FieldType::Field5(5.0)));
+        let e6 = OuterFieldType::FieldI(InnerFieldType::FieldI2(Inner2FieldType::Field6(6)));
+        let e7 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field7(7.0)));
+        let e8 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field8(8)));
+
+        let e4_2 = OuterFieldType::FieldI(InnerFieldType::Field4(42));
+        let e7_2 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field7(7.2)));
+
+        fieldset.apply(e1);
+        fieldset.apply(e2);
+        fieldset.apply(e3);
+        fieldset.apply(e4);
+        fieldset.apply(e5);
+        fieldset.apply(e6);
+        fieldset.apply(e7);
+        fieldset.apply(e8);
+
+        fieldset.apply(e4_2);
+        fieldset.apply(e7_2);
+
+        let mut iter = fieldset.into_iter();
+
+        assert_eq!(iter.next(), Some(e1));
+        assert_eq!(iter.next(), Some(e2));
+        assert_eq!(iter.next(), Some(e3));
+        assert_eq!(iter.next(), Some(e4_2));
+        assert_eq!(iter.next(), Some(e5));
+        assert_eq!(iter.next(), Some(e6));
+        assert_eq!(iter.next(), Some(e7_2));
+        assert_eq!(iter.next(), Some(e8));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    pub fn bit_field_set_full_check() {
+        let mut fieldset = OuterBitFieldSet::new();
+        let e1 = OuterFieldType::Field1(1.0);
+        let e2 = OuterFieldType::Field2(2);
+        let e3 = OuterFieldType::FieldI(InnerFieldType::Field3(3.0));
+        let e4 = OuterFieldType::FieldI(InnerFieldType::Field4(4));
+        let e5 = OuterFieldType::FieldI(InnerFieldType::FieldI2(Inner2FieldType::Field5(5.0)));
+        let e6 = OuterFieldType::FieldI(InnerFieldType::FieldI2(Inner2FieldType::Field6(6)));
+        let e7 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field7(7.0)));
+        let e8 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field8(8)));
+
+        let e4_2 = OuterFieldType::FieldI(InnerFieldType::Field4(42));
+        let e7_2 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field7(7.2)));
+
+        fieldset.apply(e1);
+        fieldset.apply(e2);
+        fieldset.apply(e3);
+        fieldset.apply(e4);
+        fieldset.apply(e5);
+        fieldset.apply(e6);
+        fieldset.apply(e7);
+        fieldset.apply(e8);
+
+        fieldset.apply(e4_2); // ignored!
+        fieldset.apply(e7_2); // ignored!
+
+        let mut iter = fieldset.into_iter();
+
+        assert_eq!(iter.next(), Some(e1));
+        assert_eq!(iter.next(), Some(e2));
+        assert_eq!(iter.next(), Some(e3));
+        assert_eq!(iter.next(), Some(e4));
+        assert_eq!(iter.next(), Some(e5));
+        assert_eq!(iter.next(), Some(e6));
+        assert_eq!(iter.next(), Some(e7));
+        assert_eq!(iter.next(), Some(e8));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    pub fn perf_field_set_full_check() {
+        let mut fieldset = OuterPerfFieldSet::new();
+        let e1 = OuterFieldType::Field1(1.0);
+        let e2 = OuterFieldType::Field2(2);
+        let e3 = OuterFieldType::FieldI(InnerFieldType::Field3(3.0));
+        let e4 = OuterFieldType::FieldI(InnerFieldType::Field4(4));
+        let e5 = OuterFieldType::FieldI(InnerFieldType::FieldI2(Inner2FieldType::Field5(5.0)));
+        let e6 = OuterFieldType::FieldI(InnerFieldType::FieldI2(Inner2FieldType::Field6(6)));
+        let e7 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field7(7.0)));
+        let e8 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field8(8)));
+
+        let e4_2 = OuterFieldType::FieldI(InnerFieldType::Field4(42));
+        let e7_2 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field7(7.2)));
+
+        fieldset.apply(e1);
+        fieldset.apply(e2);
+        fieldset.apply(e3);
+        fieldset.apply(e4);
+        fieldset.apply(e5);
+        fieldset.apply(e6);
+        fieldset.apply(e7);
+        fieldset.apply(e8);
+
+        fieldset.apply(e4_2);
+        fieldset.apply(e7_2);
+
+        let mut iter = fieldset.into_iter();
+
+        assert_eq!(iter.next(), Some(e1));
+        assert_eq!(iter.next(), Some(e2));
+        assert_eq!(iter.next(), Some(e3));
+        assert_eq!(iter.next(), Some(e4_2));
+        assert_eq!(iter.next(), Some(e5));
+        assert_eq!(iter.next(), Some(e6));
+        assert_eq!(iter.next(), Some(e7_2));
+        assert_eq!(iter.next(), Some(e8));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    pub fn named_iter_field_set_check() {
+        // The iterators can be stored without boxing or naming closures.
+        struct Iters {
+            opt: OuterOptFieldSetIter,
+            bit: OuterBitFieldSetIter,
+            perf: OuterPerfFieldSetIter,
+        }
+
+        let e2 = OuterFieldType::Field2(2);
+        let e4 = OuterFieldType::FieldI(InnerFieldType::Field4(4));
+
+        let mut opt_fieldset = OuterOptFieldSet::new();
+        let mut bit_fieldset = OuterBitFieldSet::new();
+        let mut perf_fieldset = OuterPerfFieldSet::new();
+        for_each_fieldset(
+            [&mut opt_fieldset, &mut bit_fieldset, &mut perf_fieldset],
+            |fieldset| {
+                fieldset.apply(e4);
+                fieldset.apply(e2);
+            },
+        );
+
+        let iters = Iters {
+            opt: opt_fieldset.into_iter(),
+            bit: bit_fieldset.into_iter(),
+            perf: perf_fieldset.into_iter(),
+        };
+        assert_eq!(iters.opt.collect::<Vec3<_>>(), [Some(e2), Some(e4), None]);
+        assert_eq!(iters.bit.collect::<Vec3<_>>(), [Some(e4), Some(e2), None]);
+        assert_eq!(iters.perf.collect::<Vec3<_>>(), [Some(e4), Some(e2), None]);
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, FieldSet)]
+    struct Rgb(u8, u8, #[fieldset(name = blue)] u8);
+
+    #[derive(Clone, Copy, FieldSet)]
+    struct Light(#[fieldset] Rgb, #[fieldset_skip] u32, bool);
+
+    #[derive(Clone, Copy, Debug, PartialEq, FieldSet)]
+    enum Mode {
+        Idle,
+        Running {
+            speed: u32,
+            #[fieldset]
+            color: Rgb,
+        },
+        Fault(u8),
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    struct Machine {
+        #[fieldset]
+        mode: Mode,
+        count: u32,
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub struct Payload(u32);
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Label(u32);
+
+    #[derive(FieldSet)]
+    #[fieldset(derive(Debug, PartialEq))]
+    struct Message {
+        id: u32,
+        payload: Payload,
+    }
+
+    #[derive(FieldSet)]
+    #[fieldset(derive(Clone, Debug, PartialEq))]
+    struct Labeled {
+        #[fieldset]
+        color: Rgb,
+        label: Label,
+    }
+
+    fn same_parity(a: &u32, b: &u32) -> bool {
+        a % 2 == b % 2
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    struct Sensor {
+        #[fieldset(epsilon = 0.5)]
+        temperature: f32,
+        #[fieldset(eq = same_parity)]
+        parity: u32,
+        #[fieldset]
+        inner: Inner2,
+    }
+
+    fn is_even(x: &u32) -> bool {
+        x.is_multiple_of(2)
+    }
+
+    fn is_ordered(range: &Range) -> Result<(), RangeFieldId> {
+        if range.min <= range.max {
+            Ok(())
+        } else {
+            Err(RangeFieldId::Max)
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, FieldSet)]
+    #[fieldset(validate = is_ordered)]
+    struct Range {
+        min: u32,
+        max: u32,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, FieldSet)]
+    struct Limits {
+        #[fieldset]
+        range: Range,
+        #[fieldset(validate = is_even)]
+        step: u32,
+    }
+
+    #[test]
+    pub fn generic_field_set_check() {
+        let mut fieldset = GenericOuterPerfFieldSet::<u8, 2>::new();
+        let e1 = GenericOuterFieldType::FieldC("c");
+        let e2 = GenericOuterFieldType::FieldI(GenericInnerFieldType::FieldA([1, 2]));
+        let e3 = GenericOuterFieldType::FieldI(GenericInnerFieldType::FieldB(3));
+        let e3_2 = GenericOuterFieldType::FieldI(GenericInnerFieldType::FieldB(4));
+
+        fieldset.apply(e1);
+        fieldset.apply(e3);
+        fieldset.apply(e2);
+        fieldset.apply(e3_2);
+
+        let id_b = GenericOuterFieldId::FieldI(GenericInnerFieldId::FieldB);
+        assert!(fieldset.is_modified(id_b));
+        assert_eq!(id_b.index(), 2);
+        assert_eq!(id_b.path(), "field_i.field_b");
+        assert_eq!(fieldset.pending().field_i().field_b(), Some(&4));
+
+        let mut model = GenericOuter {
+            field_c: "",
+            field_i: GenericInner {
+                field_a: [0, 0],
+                field_b: 0,
+            },
+        };
+        let mut iter = fieldset.into_iter();
+        for field_change in iter.clone() {
+            model.apply(field_change);
+        }
+
+        assert_eq!(iter.next(), Some(e1));
+        assert_eq!(iter.next(), Some(e3_2));
+        assert_eq!(iter.next(), Some(e2));
+        assert_eq!(iter.next(), None);
+
+        assert_eq!(model.field_c, "c");
+        assert_eq!(model.field_i.field_a, [1, 2]);
+        assert_eq!(model.field_i.field_b, 4);
+        assert_eq!(model.into_iter().count(), 3);
+    }
+
+    #[test]
+    pub fn path_field_set_check() {
+        use motor::{MotorModelFieldSetter, MotorModelFieldType};
+
+        let mut fieldset = PathOuterBitFieldSet::new();
+        let e1 = PathOuterFieldType::Wrapper(WrapperFieldType::Value(1));
+        let e2 = PathOuterFieldType::Motor(MotorModelFieldType::Enabled(true));
+        let e3 = PathOuterFieldType::Field(3.0);
+
+        fieldset.wrapper().value().set(1);
+        fieldset.motor().enabled().set(true);
+        fieldset.field().set(3.0);
+
+        assert_eq!(<PathOuter as FieldSetModel>::VARIANCE, 4);
+        assert_eq!(<PathOuter as FieldSetModel>::field_index(&e1), 2);
+        assert_eq!(<PathOuter as FieldSetModel>::field_index(&e2), 1);
+        assert_eq!(<PathOuter as FieldSetModel>::field_index(&e3), 3);
+
+        let mut iter = fieldset.into_iter();
+
+        assert_eq!(iter.next(), Some(e1));
+        assert_eq!(iter.next(), Some(e2));
+        assert_eq!(iter.next(), Some(e3));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    pub fn alias_field_set_check() {
+        use gyro::{AxisFieldSetter, AxisFieldType};
+
+        let mut fieldset = AliasOuterPerfFieldSet::<i16>::new();
+        let e1 = AliasOuterFieldType::Count(1);
+        let e2 = AliasOuterFieldType::Axis(AxisFieldType::Valid(true));
+
+        fieldset.count().set(1);
+        fieldset.axis().valid().set(true);
+
+        assert_eq!(<AliasOuter<i16> as FieldSetModel>::VARIANCE, 3);
+        assert_eq!(AliasOuter::<i16>::FIELDS[1].path, "axis.valid");
+        assert_eq!(e2.index(), 1);
+        assert_eq!(e2.path(), "axis.valid");
+
+        let mut iter = fieldset.into_iter();
+
+        assert_eq!(iter.next(), Some(e1));
+        assert_eq!(iter.next(), Some(e2));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    pub fn bounded_generic_field_set_check() {
+        let mut fieldset = StationIndexFieldSet::<u8>::new();
+        let e1 = StationFieldType::Id(1);
+        let e2 = StationFieldType::Reading(ReadingFieldType::Value(2));
+
+        fieldset.reading().value().set(2);
+        fieldset.id().set(1);
+
+        assert_eq!(<Station<u8> as FieldSetModel>::VARIANCE, 3);
+        assert_eq!(Station::<u8>::FIELDS[2].path, "reading.valid");
+        assert_eq!(e2.index(), 1);
+        assert!(fieldset.is_any_modified(&StationMask::READING));
+
+        let mut iter = fieldset.into_iter();
+
+        assert_eq!(iter.next(), Some(e1));
+        assert_eq!(iter.next(), Some(e2));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    pub fn tuple_field_set_check() {
+        let e1 = LightFieldType::_2(true);
+        let e2 = LightFieldType::_0(RgbFieldType::Blue(3));
+        let e3 = LightFieldType::_0(RgbFieldType::_0(1));
+        let e3_2 = LightFieldType::_0(RgbFieldType::_0(2));
+
+        let mut opt_fieldset = LightOptFieldSet::new();
+        let mut bit_fieldset = LightBitFieldSet::new();
+        let mut perf_fieldset = LightPerfFieldSet::new();
+        for_each_fieldset(
+            [&mut opt_fieldset, &mut bit_fieldset, &mut perf_fieldset],
+            |fieldset| {
+                fieldset._2().set(true);
+                fieldset._0().blue().set(3);
+                fieldset._0()._0().set(1);
+                fieldset._0()._0().set(2);
+            },
+        );
+
+        assert_eq!(<Light as FieldSetModel>::VARIANCE, 4);
+        assert_eq!(<Light as FieldSetModel>::field_index(&e1), 3);
+        assert_eq!(<Light as FieldSetModel>::field_index(&e2), 2);
+
+        let mut iter = opt_fieldset.into_iter();
+        assert_eq!(iter.next(), Some(e3_2));
+        assert_eq!(iter.next(), Some(e2));
+        assert_eq!(iter.next(), Some(e1));
+        assert_eq!(iter.next(), None);
+
+        let mut iter = bit_fieldset.into_iter();
+        assert_eq!(iter.next(), Some(e1));
+        assert_eq!(iter.next(), Some(e2));
+        assert_eq!(iter.next(), Some(e3));
+        assert_eq!(iter.next(), None);
+
+        let mut model = Light(Rgb(0, 0, 0), 7, false);
+        let mut iter = perf_fieldset.into_iter();
+        for field_change in iter.clone() {
+            model.apply(field_change);
+        }
+        assert_eq!(iter.next(), Some(e1));
+        assert_eq!(iter.next(), Some(e2));
+        assert_eq!(iter.next(), Some(e3_2));
+        assert_eq!(iter.next(), None);
+
+        assert_eq!(model.0 .0, 2);
+        assert_eq!(model.0 .2, 3);
+        assert_eq!(model.1, 7);
+        assert!(model.2);
+        assert_eq!(model.into_iter().count(), 4);
+    }
+
+    #[test]
+    pub fn enum_field_set_check() {
+        let running_1 = Mode::Running {
+            speed: 1,
+            color: Rgb(0, 0, 0),
+        };
+        let running_4 = Mode::Running {
+            speed: 4,
+            color: Rgb(1, 1, 1),
+        };
+        let e1 = MachineFieldType::Mode(ModeFieldType::RunningSpeed(5));
+        let e2 = MachineFieldType::Mode(ModeFieldType::Variant(running_1));
+        let e2_2 = MachineFieldType::Mode(ModeFieldType::Variant(running_4));
+        let e3 = MachineFieldType::Mode(ModeFieldType::RunningColor(RgbFieldType::Blue(9)));
+        let e4 = MachineFieldType::Mode(ModeFieldType::Fault0(3));
+        let e5 = MachineFieldType::Count(2);
+
+        let mut opt_fieldset = MachineOptFieldSet::new();
+        let mut bit_fieldset = MachineBitFieldSet::new();
+        let mut perf_fieldset = MachinePerfFieldSet::new();
+        for_each_fieldset(
+            [&mut opt_fieldset, &mut bit_fieldset, &mut perf_fieldset],
+            |fieldset| {
+                fieldset.apply(e1);
+                fieldset.mode().variant().set(running_1);
+                fieldset.mode().running_color().blue().set(9);
+                fieldset.mode().fault_0().set(3);
+                fieldset.count().set(2);
+                fieldset.apply(e2_2);
+            },
+        );
+
+        assert_eq!(<Mode as FieldSetModel>::VARIANCE, 6);
+        assert_eq!(<Machine as FieldSetModel>::VARIANCE, 7);
+        assert_eq!(<Machine as FieldSetModel>::field_index(&e3), 4);
+        assert_eq!(<Machine as FieldSetModel>::superseded_fields(&e2), 1..6);
+        assert!(<Machine as FieldSetModel>::superseded_fields(&e3).is_empty());
+
+        let mut iter = opt_fieldset.into_iter();
+        assert_eq!(iter.next(), Some(e2_2));
+        assert_eq!(iter.next(), Some(e5));
+        assert_eq!(iter.next(), None);
+
+        let mut model = Machine {
+            mode: Mode::Idle,
+            count: 0,
+        };
+        let mut iter = perf_fieldset.into_iter();
+        for field_change in iter.clone() {
+            model.apply(field_change);
+        }
+        assert_eq!(iter.next(), Some(e2_2));
+        assert_eq!(iter.next(), Some(e5));
+        assert_eq!(iter.next(), None);
+        assert_eq!(model.mode, running_4);
+        assert_eq!(model.count, 2);
+
+        let mut model = Machine {
+            mode: Mode::Idle,
+            count: 0,
+        };
+        let mut iter = bit_fieldset.into_iter();
+        for field_change in iter.clone() {
+            model.apply(field_change);
+        }
+        assert_eq!(iter.next(), Some(e2));
         assert_eq!(iter.next(), Some(e3));
-        assert_eq!(iter.next(), Some(e4_2));
+        assert_eq!(iter.next(), Some(e4));
         assert_eq!(iter.next(), Some(e5));
-        assert_eq!(iter.next(), Some(e6));
+        assert_eq!(iter.next(), None);
+        assert_eq!(
+            model.mode,
+            Mode::Running {
+                speed: 1,
+                color: Rgb(0, 0, 9)
+            }
+        );
+        assert_eq!(model.into_iter().count(), 2);
+    }
+
+    #[test]
+    pub fn non_copy_field_set_check() {
+        let mut bit_fieldset = MessageBitFieldSet::new();
+        let mut perf_fieldset = MessagePerfFieldSet::new();
+        for_each_fieldset([&mut bit_fieldset, &mut perf_fieldset], |fieldset| {
+            fieldset.payload().set(Payload(1));
+            fieldset.id().set(2);
+            fieldset.payload().set(Payload(3));
+        });
+
+        let mut iter = bit_fieldset.into_iter();
+        assert_eq!(iter.next(), Some(MessageFieldType::Payload(Payload(1))));
+        assert_eq!(iter.next(), Some(MessageFieldType::Id(2)));
+        assert_eq!(iter.next(), None);
+
+        let mut model = Message {
+            id: 0,
+            payload: Payload(0),
+        };
+        for field_change in perf_fieldset {
+            model.apply(field_change);
+        }
+        assert_eq!(model.id, 2);
+        assert_eq!(model.payload, Payload(3));
+
+        let mut fieldset = LabeledOptFieldSet::new();
+        fieldset.label().set(Label(4));
+        fieldset.color().blue().set(5);
+
+        let mut iter = fieldset.into_iter();
+        assert_eq!(iter.clone().count(), 2);
+        assert_eq!(
+            iter.next(),
+            Some(LabeledFieldType::Color(RgbFieldType::Blue(5)))
+        );
+        assert_eq!(iter.next(), Some(LabeledFieldType::Label(Label(4))));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    pub fn reuse_field_set_check() {
+        let e1 = LightFieldType::_2(true);
+        let e2 = LightFieldType::_0(RgbFieldType::Blue(3));
+        let e3 = LightFieldType::_0(RgbFieldType::_0(1));
+
+        let mut fieldset = LightPerfFieldSet::new();
+        fieldset._2().set(true);
+        fieldset._0().blue().set(3);
+        let mut iter = fieldset.iter();
+        assert_eq!(iter.next(), Some(&e1));
+        assert_eq!(iter.next(), Some(&e2));
+        assert_eq!(iter.next(), None);
+        let mut drain = fieldset.drain();
+        assert_eq!(drain.next(), Some(e1));
+        drop(drain);
+        assert_eq!(fieldset.iter().next(), None);
+        fieldset._0()._0().set(1);
+        fieldset._2().set(true);
+        assert_eq!(
+            fieldset.drain().collect::<Vec3<_>>(),
+            [Some(e3), Some(e1), None]
+        );
+        fieldset._0().blue().set(3);
+        fieldset.clear();
+        assert_eq!(fieldset.iter().next(), None);
+        fieldset._0().blue().set(3);
+        assert_eq!(fieldset.into_iter().next(), Some(e2));
+
+        let mut fieldset = LightBitFieldSet::new();
+        fieldset._2().set(true);
+        fieldset._0().blue().set(3);
+        assert_eq!(fieldset.iter().count(), 2);
+        assert_eq!(
+            fieldset.drain().collect::<Vec3<_>>(),
+            [Some(e1), Some(e2), None]
+        );
+        fieldset._0().blue().set(3);
+        fieldset._2().set(true);
+        assert_eq!(
+            fieldset.iter().collect::<Vec3<_>>(),
+            [Some(&e2), Some(&e1), None]
+        );
+        fieldset.clear();
+        fieldset._0()._0().set(1);
+        assert_eq!(
+            fieldset.drain().collect::<Vec3<_>>(),
+            [Some(e3), None, None]
+        );
+
+        let mut fieldset = LightOptFieldSet::new();
+        fieldset._2().set(true);
+        fieldset._0().blue().set(3);
+        assert_eq!(
+            fieldset.iter().collect::<Vec3<_>>(),
+            [Some(e2), Some(e1), None]
+        );
+        assert_eq!(
+            fieldset.drain().collect::<Vec3<_>>(),
+            [Some(e2), Some(e1), None]
+        );
+        assert_eq!(fieldset.iter().next(), None);
+        fieldset._0()._0().set(1);
+        fieldset.clear();
+        assert_eq!(fieldset.into_iter().next(), None);
+    }
+
+    #[test]
+    pub fn clear_touched_slots_check() {
+        let e1 = LightFieldType::_2(true);
+        let e3 = LightFieldType::_0(RgbFieldType::_0(1));
+        let blue = LightFieldId::_0(RgbFieldId::Blue);
+
+        // The fields are untracked along with their slots, so they are recorded anew after the
+        // reset, once each.
+        let mut fieldset = LightPerfFieldSet::new();
+        fieldset._0().blue().set(3);
+        fieldset._2().set(true);
+        fieldset.clear();
+        assert_eq!(fieldset.len(), 0);
+        assert!(!fieldset.is_modified(blue));
+        assert_eq!(fieldset.pending()._2(), None);
+        assert_eq!(fieldset.iter().next(), None);
+        fieldset._2().set(false);
+        fieldset._0()._0().set(1);
+        fieldset._2().set(true);
+        assert_eq!(fieldset.len(), 2);
+        assert_eq!(
+            fieldset.iter().collect::<Vec3<_>>(),
+            [Some(&e1), Some(&e3), None]
+        );
+
+        let mut fieldset = LightBitFieldSet::new();
+        fieldset._0().blue().set(3);
+        fieldset._2().set(false);
+        fieldset.clear();
+        assert_eq!(fieldset.len(), 0);
+        assert!(!fieldset.is_modified(blue));
+        assert_eq!(fieldset.pending()._0().blue(), None);
+        assert_eq!(fieldset.iter().next(), None);
+        fieldset._2().set(true);
+        fieldset._0()._0().set(1);
+        assert_eq!(
+            fieldset.iter().collect::<Vec3<_>>(),
+            [Some(&e1), Some(&e3), None]
+        );
+
+        let mut fieldset = LightIndexFieldSet::new();
+        fieldset._0().blue().set(3);
+        fieldset._2().set(false);
+        fieldset.clear();
+        assert_eq!(fieldset.len(), 0);
+        assert!(!fieldset.is_modified(blue));
+        assert_eq!(fieldset.pending()._2(), None);
+        assert_eq!(fieldset.iter().next(), None);
+        fieldset._2().set(true);
+        fieldset._0()._0().set(1);
+        assert_eq!(fieldset.len(), 2);
+        assert_eq!(
+            fieldset.iter().collect::<Vec3<_>>(),
+            [Some(&e3), Some(&e1), None]
+        );
+    }
+
+    #[test]
+    pub fn query_field_set_check() {
+        fn pending_field_4<'p>(pending: impl OuterFieldGetter<'p>) -> Option<u32> {
+            pending.field_i().field_4().copied()
+        }
+
+        let id_1 = OuterFieldId::Field1;
+        let id_4 = OuterFieldId::FieldI(InnerFieldId::Field4);
+        let id_8 = OuterFieldId::FieldI(InnerFieldId::FieldI3(Inner3FieldId::Field8));
+        assert_eq!(id_1.index(), 0);
+        assert_eq!(id_4.index(), 3);
+        assert_eq!(id_8.index(), 7);
+
+        let mut opt_fieldset = OuterOptFieldSet::new();
+        let mut bit_fieldset = OuterBitFieldSet::new();
+        let mut perf_fieldset = OuterPerfFieldSet::new();
+        assert!(opt_fieldset.is_empty());
+        assert!(bit_fieldset.is_empty());
+        assert!(perf_fieldset.is_empty());
+        for_each_fieldset(
+            [&mut opt_fieldset, &mut bit_fieldset, &mut perf_fieldset],
+            |fieldset| {
+                fieldset.field_2().set(2);
+                fieldset.field_i().field_4().set(4);
+                fieldset.field_i().field_i3().field_8().set(8);
+                fieldset.field_i().field_4().set(5);
+            },
+        );
+
+        assert_eq!(opt_fieldset.len(), 3);
+        assert!(opt_fieldset.is_modified(id_4));
+        assert!(!opt_fieldset.is_modified(id_1));
+        assert_eq!(opt_fieldset.pending().field_2(), Some(&2));
+        assert_eq!(opt_fieldset.pending().field_1(), None);
+        assert_eq!(
+            opt_fieldset.pending().field_i().field_i3().field_8(),
+            Some(&8)
+        );
+        assert_eq!(pending_field_4(opt_fieldset.pending()), Some(5));
+
+        assert_eq!(bit_fieldset.len(), 3);
+        assert!(bit_fieldset.is_modified(id_8));
+        assert!(!bit_fieldset.is_modified(id_1));
+        assert_eq!(bit_fieldset.pending().field_2(), Some(&2));
+        assert_eq!(bit_fieldset.pending().field_1(), None);
+        assert_eq!(
+            bit_fieldset.pending().field_i().field_i3().field_8(),
+            Some(&8)
+        );
+        assert_eq!(pending_field_4(bit_fieldset.pending()), Some(4));
+
+        assert_eq!(perf_fieldset.len(), 3);
+        assert!(perf_fieldset.is_modified(id_4));
+        assert!(!perf_fieldset.is_modified(id_1));
+        assert_eq!(perf_fieldset.pending().field_2(), Some(&2));
+        assert_eq!(perf_fieldset.pending().field_1(), None);
+        assert_eq!(
+            perf_fieldset.pending().field_i().field_i3().field_8(),
+            Some(&8)
+        );
+        assert_eq!(pending_field_4(perf_fieldset.pending()), Some(5));
+        perf_fieldset.clear();
+        assert!(perf_fieldset.is_empty());
+        assert!(!perf_fieldset.is_modified(id_4));
+    }
+
+    // `len` is a method of the storages, so its setter is renamed.
+    #[derive(Clone, Copy, FieldSet)]
+    struct Packet {
+        #[fieldset(name = length)]
+        len: u16,
+        kind: u8,
+    }
+
+    #[test]
+    pub fn reserved_name_field_set_check() {
+        let mut fieldset = PacketPerfFieldSet::new();
+        fieldset.length().set(3);
+        assert_eq!(fieldset.len(), 1);
+        assert_eq!(fieldset.pending().length(), Some(&3));
+
+        let mut model = Packet { len: 0, kind: 1 };
+        for field_change in fieldset {
+            model.apply(field_change);
+        }
+        assert_eq!(model.len, 3);
+    }
+
+    #[allow(clippy::upper_case_acronyms)]
+    #[derive(Clone, Copy, FieldSet)]
+    struct HTTPServer {
+        port: u16,
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    struct HttpServer {
+        port: u16,
+        enabled: bool,
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    #[fieldset(codec)]
+    #[cfg_attr(feature = "serde", fieldset(serde))]
+    struct Empty {}
+
+    #[derive(Clone, Copy, FieldSet)]
+    struct AllSkipped {
+        #[fieldset_skip]
+        _field: u32,
+    }
+
+    #[test]
+    pub fn empty_field_set_check() {
+        assert_eq!(<Empty as FieldSetModel>::VARIANCE, 0);
+        assert!(Empty::FIELDS.is_empty());
+        assert_eq!(EmptyMask::all(), EmptyMask::empty());
+        assert!(EmptyOptFieldSet::new().into_iter().next().is_none());
+        assert!(EmptyPerfFieldSet::new().is_empty());
+        assert!(Empty {}.into_iter().next().is_none());
+
+        let mut fieldset = AllSkippedBitFieldSet::new();
+        fieldset.clear();
+        assert_eq!(fieldset.len(), 0);
+        assert!(fieldset.into_iter().next().is_none());
+        assert!(AllSkippedIndexFieldSet::new().iter().next().is_none());
+    }
+
+    #[test]
+    pub fn field_metadata_check() {
+        let e4 = OuterFieldType::FieldI(InnerFieldType::Field4(4));
+        assert_eq!(e4.id(), OuterFieldId::FieldI(InnerFieldId::Field4));
+        assert_eq!(e4.index(), 3);
+        assert_eq!(e4.name(), "field_4");
+        assert_eq!(e4.path(), "field_i.field_4");
+        assert_eq!(OuterFieldId::Field2.path(), "field_2");
+
+        assert_eq!(Outer::FIELDS.len(), <Outer as FieldSetModel>::VARIANCE);
+        assert_eq!(<Outer as FieldSetModel>::FIELDS, Outer::FIELDS);
+        for (i, field) in Outer::FIELDS.iter().enumerate() {
+            assert_eq!(field.index, i);
+        }
+        assert_eq!(
+            Outer::FIELDS[7],
+            FieldInfo {
+                index: 7,
+                name: "field_8",
+                path: "field_i.field_i3.field_8",
+            }
+        );
+        assert_eq!(Inner::FIELDS[5].path, "field_i3.field_8");
+
+        assert_eq!(GenericOuter::<u8, 2>::FIELDS[1].path, "field_i.field_a");
+        assert_eq!(Light::FIELDS[2].path, "_0.blue");
+        assert_eq!(Machine::FIELDS[0].path, "mode.variant");
+        assert_eq!(Machine::FIELDS[4].path, "mode.running_color.blue");
+        assert_eq!(
+            MachineFieldType::Mode(ModeFieldType::Fault0(1)).path(),
+            "mode.fault_0"
+        );
+
+        assert_eq!(HTTPServer::FIELDS.len(), 1);
+        assert_eq!(HttpServer::FIELDS[1].path, "enabled");
+        assert_eq!(Raw::FIELDS[0].name, "type");
+        assert_eq!(RawFieldId::RType.path(), "type");
+    }
+
+    #[test]
+    pub fn diff_field_set_check() {
+        let old = Outer {
+            field_1: 1.0,
+            field_2: 2,
+            field_i: Inner {
+                field_3: 3.0,
+                field_4: 4,
+                field_i2: Inner2 {
+                    field_5: 5.0,
+                    field_6: 6,
+                },
+                field_i3: Inner3 {
+                    field_7: 7.0,
+                    field_8: 8,
+                    field_skipped: 0.0,
+                },
+            },
+        };
+        let mut new = old;
+        new.field_2 = 20;
+        new.field_i.field_i3.field_8 = 80;
+        new.field_i.field_i3.field_skipped = 1.0;
+        let mut fieldset = OuterPerfFieldSet::new();
+        Outer::diff(&old, &old, &mut fieldset);
+        assert!(fieldset.is_empty());
+        Outer::diff(&old, &new, &mut fieldset);
+        assert_eq!(
+            fieldset.into_iter().collect::<Vec3<_>>(),
+            [
+                Some(OuterFieldType::Field2(20)),
+                Some(OuterFieldType::FieldI(InnerFieldType::FieldI3(
+                    Inner3FieldType::Field8(80)
+                ))),
+                None
+            ]
+        );
+
+        let old = Machine {
+            mode: Mode::Running {
+                speed: 1,
+                color: Rgb(1, 2, 3),
+            },
+            count: 0,
+        };
+        let new = Machine {
+            mode: Mode::Running {
+                speed: 1,
+                color: Rgb(1, 2, 4),
+            },
+            count: 1,
+        };
+        let mut fieldset = MachineOptFieldSet::new();
+        <Machine as FieldSetDiff>::diff(&old, &new, &mut fieldset);
+        assert_eq!(
+            fieldset.into_iter().collect::<Vec3<_>>(),
+            [
+                Some(MachineFieldType::Mode(ModeFieldType::RunningColor(
+                    RgbFieldType::Blue(4)
+                ))),
+                Some(MachineFieldType::Count(1)),
+                None
+            ]
+        );
+
+        let diff = |old: Mode, new: Mode| {
+            let mut fieldset = ModePerfFieldSet::new();
+            Mode::diff(&old, &new, &mut fieldset);
+            fieldset.into_iter().collect::<Vec3<_>>()
+        };
+        assert_eq!(diff(Mode::Idle, Mode::Idle), [None, None, None]);
+        assert_eq!(
+            diff(Mode::Fault(1), Mode::Fault(2)),
+            [Some(ModeFieldType::Fault0(2)), None, None]
+        );
+        assert_eq!(
+            diff(Mode::Idle, Mode::Fault(2)),
+            [Some(ModeFieldType::Variant(Mode::Fault(2))), None, None]
+        );
+    }
+
+    #[test]
+    pub fn dedup_field_set_check() {
+        let model = Sensor {
+            temperature: 20.0,
+            parity: 1,
+            inner: Inner2 {
+                field_5: 5.0,
+                field_6: 6,
+            },
+        };
+        let mut fieldset = SensorPerfFieldSet::new();
+        let mut setter = DedupFieldSetter::new(&model, &mut fieldset);
+        setter.temperature().set(20.4);
+        setter.parity().set(3);
+        setter.inner().field_6().set(6);
+        assert!(fieldset.is_empty());
+        let mut setter = DedupFieldSetter::new(&model, &mut fieldset);
+        setter.temperature().set(21.0);
+        setter.inner().field_5().set(5.5);
+        setter.temperature().set(20.0);
+        assert_eq!(
+            fieldset.into_iter().collect::<Vec3<_>>(),
+            [
+                Some(SensorFieldType::Temperature(20.0)),
+                Some(SensorFieldType::Inner(Inner2FieldType::Field5(5.5))),
+                None
+            ]
+        );
+
+        let running = |speed| Mode::Running {
+            speed,
+            color: Rgb(1, 2, 3),
+        };
+        let model = Machine {
+            mode: running(1),
+            count: 0,
+        };
+        let mut fieldset = MachinePerfFieldSet::new();
+        let mut setter = DedupFieldSetter::new(&model, &mut fieldset);
+        setter.mode().running_speed().set(1);
+        setter.mode().running_color().blue().set(3);
+        setter.count().set(0);
+        setter.mode().variant().set(running(1));
+        setter.mode().fault_0().set(1);
+        assert!(fieldset.is_empty());
+        let mut setter = DedupFieldSetter::new(&model, &mut fieldset);
+        setter.mode().variant().set(running(0));
+        setter.mode().running_speed().set(1);
+        setter.mode().fault_0().set(1);
+        assert_eq!(
+            fieldset.into_iter().collect::<Vec3<_>>(),
+            [
+                Some(MachineFieldType::Mode(ModeFieldType::Variant(running(0)))),
+                Some(MachineFieldType::Mode(ModeFieldType::RunningSpeed(1))),
+                Some(MachineFieldType::Mode(ModeFieldType::Fault0(1)))
+            ]
+        );
+    }
+
+    #[test]
+    pub fn layered_field_set_check() {
+        let model = Sensor {
+            temperature: 20.0,
+            parity: 1,
+            inner: Inner2 {
+                field_5: 5.0,
+                field_6: 6,
+            },
+        };
+        let mut fieldset = SensorPerfFieldSet::new();
+        let mut setter = LayeredFieldSetter::new(&model, &mut fieldset);
+        assert_eq!(setter.parity().get(), Some(1));
+        setter.parity().update(|x| x + 1);
+        setter.parity().update(|x| x + 1);
+        assert_eq!(setter.parity().get(), Some(3));
+        setter.inner().field_6().update(|x| x * 2);
+        assert_eq!(setter.inner().field_6().get(), Some(12));
+        assert_eq!(setter.inner().field_5().get(), Some(5.0));
+        assert_eq!(
+            fieldset.into_iter().collect::<Vec3<_>>(),
+            [
+                Some(SensorFieldType::Parity(3)),
+                Some(SensorFieldType::Inner(Inner2FieldType::Field6(12))),
+                None
+            ]
+        );
+
+        let model = Machine {
+            mode: Mode::Running {
+                speed: 1,
+                color: Rgb(1, 2, 3),
+            },
+            count: 0,
+        };
+        let mut fieldset = MachinePerfFieldSet::new();
+        let mut setter = LayeredFieldSetter::new(&model, &mut fieldset);
+        assert_eq!(setter.mode().running_speed().get(), Some(1));
+        assert_eq!(setter.mode().running_color().blue().get(), Some(3));
+        assert_eq!(setter.mode().fault_0().get(), None);
+        setter.mode().fault_0().update(|x| x + 1);
+        setter.mode().variant().set(Mode::Fault(4));
+        assert_eq!(setter.mode().running_speed().get(), None);
+        assert_eq!(setter.mode().fault_0().get(), Some(4));
+        setter.mode().fault_0().update(|x| x + 1);
+        assert_eq!(setter.mode().fault_0().get(), Some(5));
+        setter.count().update(|x| x + 1);
+        assert_eq!(
+            fieldset.into_iter().collect::<Vec3<_>>(),
+            [
+                Some(MachineFieldType::Mode(ModeFieldType::Variant(Mode::Fault(
+                    4
+                )))),
+                Some(MachineFieldType::Mode(ModeFieldType::Fault0(5))),
+                Some(MachineFieldType::Count(1))
+            ]
+        );
+
+        let mut fieldset = MachinePerfFieldSet::new();
+        let mut setter = DedupFieldSetter::new(&model, &mut fieldset);
+        setter.count().update(|x| x);
+        setter.mode().running_speed().update(|x| x + 1);
+        assert_eq!(
+            fieldset.into_iter().collect::<Vec3<_>>(),
+            [
+                Some(MachineFieldType::Mode(ModeFieldType::RunningSpeed(2))),
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    pub fn undo_field_set_check() {
+        let mut model = Outer {
+            field_1: 1.0,
+            field_2: 2,
+            field_i: Inner {
+                field_3: 3.0,
+                field_4: 4,
+                field_i2: Inner2 {
+                    field_5: 5.0,
+                    field_6: 6,
+                },
+                field_i3: Inner3 {
+                    field_7: 7.0,
+                    field_8: 8,
+                    field_skipped: 0.0,
+                },
+            },
+        };
+        assert_eq!(
+            model.apply_with_undo(OuterFieldType::Field2(20)),
+            Some(OuterFieldType::Field2(2))
+        );
+        assert_eq!(
+            model.apply_with_undo(OuterFieldType::FieldI(InnerFieldType::FieldI3(
+                Inner3FieldType::Field8(80)
+            ))),
+            Some(OuterFieldType::FieldI(InnerFieldType::FieldI3(
+                Inner3FieldType::Field8(8)
+            )))
+        );
+        assert_eq!(model.field_2, 20);
+        assert_eq!(model.field_i.field_i3.field_8, 80);
+
+        let original = Machine {
+            mode: Mode::Running {
+                speed: 1,
+                color: Rgb(1, 2, 3),
+            },
+            count: 0,
+        };
+        let mut model = original;
+        assert_eq!(
+            model.apply_with_undo(MachineFieldType::Mode(ModeFieldType::Fault0(1))),
+            None
+        );
+        let batch = [
+            MachineFieldType::Mode(ModeFieldType::RunningSpeed(5)),
+            MachineFieldType::Mode(ModeFieldType::RunningColor(RgbFieldType::Blue(9))),
+            MachineFieldType::Mode(ModeFieldType::Variant(Mode::Fault(1))),
+            MachineFieldType::Mode(ModeFieldType::Fault0(2)),
+            MachineFieldType::Count(3),
+            MachineFieldType::Count(4),
+        ];
+
+        let mut inverse = MachinePerfFieldSet::new();
+        model.apply_with_inverse(batch, &mut inverse);
+        assert_eq!(model.mode, Mode::Fault(2));
+        assert_eq!(model.count, 4);
+        assert_eq!(
+            inverse.iter().cloned().collect::<Vec3<_>>(),
+            [
+                Some(MachineFieldType::Mode(ModeFieldType::Variant(
+                    original.mode
+                ))),
+                Some(MachineFieldType::Count(0)),
+                None
+            ]
+        );
+        for field in inverse {
+            model.apply(field);
+        }
+        assert_eq!(model.mode, original.mode);
+        assert_eq!(model.count, original.count);
+
+        let mut inverse = MachineBitFieldSet::new();
+        model.apply_with_inverse(batch, &mut inverse);
+        for field in inverse {
+            model.apply(field);
+        }
+        assert_eq!(model.mode, original.mode);
+        assert_eq!(model.count, original.count);
+    }
+
+    #[test]
+    pub fn validate_field_set_check() {
+        let original = Limits {
+            range: Range { min: 1, max: 5 },
+            step: 2,
+        };
+        let mut model = original;
+
+        let mut fieldset = LimitsBitFieldSet::new();
+        fieldset.range().max().set(10);
+        fieldset.step().set(3);
+        assert_eq!(model.try_apply_all(fieldset), Err(LimitsFieldId::Step));
+        assert_eq!(model, original);
+
+        let mut fieldset = LimitsPerfFieldSet::new();
+        fieldset.step().set(4);
+        fieldset.range().min().set(8);
+        fieldset.range().max().set(6);
+        assert_eq!(
+            model.try_apply_all(fieldset),
+            Err(LimitsFieldId::Range(RangeFieldId::Max))
+        );
+        assert_eq!(model, original);
+
+        let mut fieldset = LimitsOptFieldSet::default();
+        fieldset.range().max().set(9);
+        fieldset.range().min().set(8);
+        fieldset.step().set(4);
+        assert_eq!(model.try_apply_all(fieldset), Ok(()));
+        assert_eq!(
+            model,
+            Limits {
+                range: Range { min: 8, max: 9 },
+                step: 4,
+            }
+        );
+    }
+
+    #[test]
+    pub fn log_field_set_check() {
+        let mut fieldset = LogFieldSet::<OuterFieldType, 3>::new(LogOverflow::DropOldest);
+        fieldset.field_2().set(1);
+        fieldset.field_i().field_i3().field_8().set(2);
+        fieldset.field_2().set(3);
+        assert_eq!(fieldset.len(), 3);
+        assert_eq!(
+            fieldset.iter().cloned().collect::<Vec3<_>>(),
+            [
+                Some(OuterFieldType::Field2(1)),
+                Some(OuterFieldType::FieldI(InnerFieldType::FieldI3(
+                    Inner3FieldType::Field8(2)
+                ))),
+                Some(OuterFieldType::Field2(3))
+            ]
+        );
+        fieldset.field_1().set(4.0);
+        assert_eq!(fieldset.check(), Ok(()));
+        assert_eq!(fieldset.dropped(), 1);
+        assert_eq!(
+            fieldset.drain().collect::<Vec3<_>>(),
+            [
+                Some(OuterFieldType::FieldI(InnerFieldType::FieldI3(
+                    Inner3FieldType::Field8(2)
+                ))),
+                Some(OuterFieldType::Field2(3)),
+                Some(OuterFieldType::Field1(4.0))
+            ]
+        );
+        assert!(fieldset.is_empty());
+        for value in 5..9 {
+            fieldset.field_2().set(value);
+        }
+        assert_eq!(fieldset.iter().count(), 3);
+        assert_eq!(
+            fieldset.into_iter().collect::<Vec3<_>>(),
+            [
+                Some(OuterFieldType::Field2(6)),
+                Some(OuterFieldType::Field2(7)),
+                Some(OuterFieldType::Field2(8))
+            ]
+        );
+
+        let mut fieldset = LogFieldSet::<MachineFieldType, 2>::new(LogOverflow::DropNewest);
+        fieldset.count().set(1);
+        fieldset.mode().variant().set(Mode::Fault(2));
+        fieldset.count().set(3);
+        assert_eq!(fieldset.check(), Ok(()));
+        assert_eq!(fieldset.dropped(), 1);
+        assert_eq!(
+            fieldset.into_iter().collect::<Vec3<_>>(),
+            [
+                Some(MachineFieldType::Count(1)),
+                Some(MachineFieldType::Mode(ModeFieldType::Variant(Mode::Fault(
+                    2
+                )))),
+                None
+            ]
+        );
+
+        let mut fieldset = LogFieldSet::<MachineFieldType, 2>::new(LogOverflow::Error);
+        fieldset.count().set(1);
+        fieldset.count().set(2);
+        assert_eq!(fieldset.check(), Ok(()));
+        fieldset.count().set(3);
+        assert_eq!(fieldset.check(), Err(LogFull));
+        assert_eq!(fieldset.push(MachineFieldType::Count(4)), Err(LogFull));
+        assert_eq!(fieldset.dropped(), 2);
+        assert_eq!(
+            fieldset.iter().cloned().collect::<Vec3<_>>(),
+            [
+                Some(MachineFieldType::Count(1)),
+                Some(MachineFieldType::Count(2)),
+                None
+            ]
+        );
+        fieldset.clear();
+        assert_eq!(fieldset.check(), Ok(()));
+        assert_eq!(fieldset.dropped(), 0);
+        assert_eq!(fieldset.push(MachineFieldType::Count(4)), Ok(()));
+    }
+
+    #[test]
+    pub fn index_field_set_full_check() {
+        let mut fieldset = OuterIndexFieldSet::new();
+        let e1 = OuterFieldType::Field1(1.0);
+        let e2 = OuterFieldType::Field2(2);
+        let e4 = OuterFieldType::FieldI(InnerFieldType::Field4(4));
+        let e7 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field7(7.0)));
+        let e8 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field8(8)));
+
+        let e4_2 = OuterFieldType::FieldI(InnerFieldType::Field4(42));
+        let e7_2 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field7(7.2)));
+
+        fieldset.apply(e8);
+        fieldset.apply(e7);
+        fieldset.apply(e2);
+        fieldset.apply(e4);
+        fieldset.apply(e1);
+
+        fieldset.apply(e4_2); // overwritten in place
+        fieldset.apply(e7_2); // overwritten in place
+
+        assert_eq!(fieldset.len(), 5);
+        assert_eq!(fieldset.pending().field_i().field_4(), Some(&42));
+        assert_eq!(
+            fieldset.iter().take(3).cloned().collect::<Vec3<_>>(),
+            [Some(e1), Some(e2), Some(e4_2)]
+        );
+
+        let mut iter: OuterIndexFieldSetIter = fieldset.into_iter();
+
+        assert_eq!(iter.next(), Some(e1));
+        assert_eq!(iter.next(), Some(e2));
+        assert_eq!(iter.next(), Some(e4_2));
         assert_eq!(iter.next(), Some(e7_2));
         assert_eq!(iter.next(), Some(e8));
         assert_eq!(iter.next(), None);
+
+        let mut fieldset = MachineIndexFieldSet::new();
+        fieldset.count().set(1);
+        fieldset.mode().running_speed().set(2);
+        fieldset.mode().variant().set(Mode::Fault(3));
+        fieldset.mode().fault_0().set(4);
+        assert_eq!(
+            fieldset.drain().collect::<Vec3<_>>(),
+            [
+                Some(MachineFieldType::Mode(ModeFieldType::Variant(Mode::Fault(
+                    3
+                )))),
+                Some(MachineFieldType::Mode(ModeFieldType::Fault0(4))),
+                Some(MachineFieldType::Count(1))
+            ]
+        );
+        assert!(fieldset.is_empty());
+        assert!(!fieldset.is_modified(MachineFieldId::Count));
+        assert_eq!(fieldset.iter().next(), None);
+    }
+
+    #[test]
+    pub fn sorted_field_set_check() {
+        let e1 = OuterFieldType::Field1(1.0);
+        let e4 = OuterFieldType::FieldI(InnerFieldType::Field4(4));
+        let e8 = OuterFieldType::FieldI(InnerFieldType::FieldI3(Inner3FieldType::Field8(8)));
+
+        let mut fieldset = OuterPerfFieldSet::new();
+        fieldset.apply(e8);
+        fieldset.apply(e1);
+        fieldset.apply(e4);
+        fieldset.sort();
+        assert_eq!(
+            fieldset.iter().cloned().collect::<Vec3<_>>(),
+            [Some(e1), Some(e4), Some(e8)]
+        );
+        fieldset.field_i().field_4().set(42);
+        assert_eq!(fieldset.pending().field_i().field_4(), Some(&42));
+        assert_eq!(fieldset.pending().field_1(), Some(&1.0));
+
+        let mut fieldset = OuterBitFieldSet::new();
+        fieldset.apply(e4);
+        fieldset.apply(e8);
+        fieldset.apply(e1);
+        fieldset.sort();
+        assert_eq!(
+            fieldset.into_iter().collect::<Vec3<_>>(),
+            [Some(e1), Some(e4), Some(e8)]
+        );
+
+        let running = Mode::Running {
+            speed: 1,
+            color: Rgb(1, 2, 3),
+        };
+        let mut fieldset = MachineBitFieldSet::new();
+        fieldset.mode().running_speed().set(5);
+        fieldset.count().set(1);
+        fieldset.mode().variant().set(running);
+        fieldset.mode().running_color().blue().set(9);
+        fieldset.sort();
+        assert_eq!(
+            fieldset.drain().collect::<Vec3<_>>(),
+            [
+                Some(MachineFieldType::Mode(ModeFieldType::Variant(running))),
+                Some(MachineFieldType::Mode(ModeFieldType::RunningColor(
+                    RgbFieldType::Blue(9)
+                ))),
+                Some(MachineFieldType::Count(1))
+            ]
+        );
+        assert!(!fieldset.is_modified(MachineFieldId::Mode(ModeFieldId::RunningSpeed)));
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    #[fieldset(perf_index = u16)]
+    struct Dimmer(#[fieldset] Rgb, bool);
+
+    #[test]
+    pub fn perf_index_check() {
+        fn max_position<I: PerfIndex>(_: &[I]) -> usize {
+            I::MAX
+        }
+
+        assert_eq!(perf_index_bits(255), 8);
+        assert_eq!(perf_index_bits(256), 16);
+        assert_eq!(perf_index_bits(65536), 32);
+        assert_eq!(
+            max_position(&OuterPerfFieldSet::new().bitset),
+            u8::MAX as usize
+        );
+        assert_eq!(
+            max_position(&DimmerPerfFieldSet::new().bitset),
+            u16::MAX as usize
+        );
+
+        let mut fieldset = DimmerPerfFieldSet::new();
+        fieldset._1().set(true);
+        fieldset._0().blue().set(3);
+        fieldset._1().set(false);
+        assert_eq!(
+            fieldset.into_iter().collect::<Vec3<_>>(),
+            [
+                Some(DimmerFieldType::_1(false)),
+                Some(DimmerFieldType::_0(RgbFieldType::Blue(3))),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    pub fn queue_field_set_check() {
+        let mut queue = ChangeQueue::<MachineFieldType, 2>::new();
+        let (mut producer, mut consumer) = queue.split();
+        assert_eq!(consumer.next(), None);
+        producer.count().set(1);
+        producer.mode().variant().set(Mode::Fault(2));
+        producer.count().set(3);
+        assert_eq!(producer.len(), 2);
+        assert_eq!(consumer.next(), Some(MachineFieldType::Count(1)));
+        assert_eq!(consumer.take_dropped(), 1);
+        assert_eq!(consumer.take_dropped(), 0);
+        producer.count().set(4);
+        assert_eq!(
+            consumer.by_ref().collect::<Vec3<_>>(),
+            [
+                Some(MachineFieldType::Mode(ModeFieldType::Variant(Mode::Fault(
+                    2
+                )))),
+                Some(MachineFieldType::Count(4)),
+                None
+            ]
+        );
+        assert!(consumer.is_empty());
+        for count in 5..10 {
+            assert_eq!(producer.push(MachineFieldType::Count(count)), Ok(()));
+            assert_eq!(consumer.next(), Some(MachineFieldType::Count(count)));
+        }
+        assert_eq!(consumer.take_dropped(), 0);
+
+        static QUEUE: ChangeQueue<MachineFieldType, 2> = ChangeQueue::new();
+        let (mut producer, mut consumer) = QUEUE.split_static().unwrap();
+        assert!(QUEUE.split_static().is_none());
+        producer.count().set(1);
+        assert_eq!(consumer.next(), Some(MachineFieldType::Count(1)));
+        assert_eq!(consumer.next(), None);
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    #[cfg_attr(feature = "serde", fieldset(serde))]
+    struct Raw {
+        r#type: u8,
+        value: u16,
+    }
+
+    #[test]
+    pub fn dispatch_field_set_check() {
+        let mut speeds = 0;
+        let mut colors = 0;
+        let mut modes = 0;
+        let mut counts = 0;
+        let mut on_speed = Subscription::new(
+            MachinePath::MODE.then(ModePath::RUNNING_SPEED),
+            |speed: &u32| speeds += speed,
+        );
+        let mut on_color = Subscription::new(
+            MachinePath::MODE.then(ModePath::RUNNING_COLOR),
+            |_: &RgbFieldType| colors += 1,
+        );
+        let mut on_mode = Subscription::new(MachinePath::MODE, |_: &ModeFieldType| modes += 1);
+        let mut on_count = Subscription::new(MachinePath::COUNT, |count: &u32| counts += count);
+        let mut dispatcher = Dispatcher::<Machine, 4>::new();
+        dispatcher.subscribe(&mut on_speed).unwrap();
+        dispatcher.subscribe(&mut on_color).unwrap();
+        dispatcher.subscribe(&mut on_mode).unwrap();
+        dispatcher.subscribe(&mut on_count).unwrap();
+        assert_eq!(dispatcher.len(), 4);
+
+        let mut fieldset = MachinePerfFieldSet::new();
+        fieldset.mode().running_speed().set(5);
+        fieldset.mode().running_color().blue().set(9);
+        fieldset.mode().running_color()._0().set(1);
+        fieldset.count().set(2);
+        dispatcher.dispatch(fieldset.iter());
+        fieldset.mode().fault_0().set(7);
+        dispatcher.dispatch(fieldset);
+        assert_eq!((speeds, colors, modes, counts), (10, 4, 7, 4));
+
+        let mut blues = 0;
+        let mut lights = 0;
+        let mut on_blue =
+            Subscription::new(LightPath::_0.then(RgbPath::BLUE), |blue: &u8| blues += blue);
+        let mut on_red = MaskSubscription::new(LightFieldId::_0(RgbFieldId::_0), |field: &_| {
+            assert_eq!(*field, LightFieldType::_0(RgbFieldType::_0(1)))
+        });
+        let mut on_light =
+            MaskSubscription::new(LightMask::_0 | LightMask::_2, |_: &_| lights += 1);
+        let mut dispatcher = Dispatcher::<Light, 3>::new();
+        dispatcher.subscribe(&mut on_blue).unwrap();
+        dispatcher.subscribe(&mut on_red).unwrap();
+        dispatcher.subscribe(&mut on_light).unwrap();
+        let mut fieldset = LightOptFieldSet::new();
+        fieldset._0()._0().set(1);
+        fieldset._0().blue().set(3);
+        fieldset._2().set(true);
+        dispatcher.dispatch(fieldset);
+        assert_eq!((blues, lights), (3, 3));
+
+        let mut types = 0;
+        let mut on_type = Subscription::new(RawPath::TYPE, |kind: &u8| types += kind);
+        let mut dispatcher = Dispatcher::<Raw, 1>::new();
+        dispatcher.subscribe(&mut on_type).unwrap();
+        let mut fieldset = RawOptFieldSet::new();
+        fieldset.r#type().set(4);
+        fieldset.value().set(5);
+        dispatcher.dispatch(fieldset);
+        assert_eq!(types, 4);
+
+        let mut rejected = Subscription::new(MachinePath::COUNT, |_: &u32| {});
+        let mut dispatcher = Dispatcher::<Machine, 0>::new();
+        assert_eq!(dispatcher.subscribe(&mut rejected), Err(DispatcherFull));
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    struct Flags {
+        all: bool,
+        empty: bool,
+    }
+
+    #[test]
+    pub fn mask_field_set_check() {
+        const SCALARS: OuterMask = OuterMask::FIELD_1.union(OuterMask::FIELD_2);
+        let subtree = OuterMask::FIELD_I;
+        assert!(OuterMask::empty().is_empty());
+        assert_eq!(SCALARS | subtree, OuterMask::all());
+        assert!(!SCALARS.intersects(&subtree));
+
+        let mut fieldset = OuterBitFieldSet::new();
+        assert!(!fieldset.is_any_modified(&OuterMask::all()));
+        fieldset.field_i().field_i3().field_8().set(8);
+        assert!(fieldset.is_any_modified(&subtree));
+        assert!(!fieldset.is_any_modified(&SCALARS));
+        fieldset.clear();
+        assert!(!fieldset.is_any_modified(&subtree));
+
+        let mut mask = MachineMask::COUNT;
+        mask |= MachineMask::MODE;
+        let mut fieldset = MachineIndexFieldSet::new();
+        fieldset.mode().running_color().blue().set(1);
+        assert!(fieldset.is_any_modified(&mask));
+        assert!(!fieldset.is_any_modified(&MachineMask::COUNT));
+
+        assert_eq!(FlagsMask::ALL | FlagsMask::EMPTY, FlagsMask::all());
+        let mut fieldset = FlagsPerfFieldSet::new();
+        fieldset.empty().set(true);
+        assert!(fieldset.is_any_modified(&FlagsMask::EMPTY));
+        assert!(!fieldset.is_any_modified(&FlagsMask::ALL));
+
+        let mut fieldset = RawBitFieldSet::new();
+        fieldset.r#type().set(1);
+        assert!(fieldset.is_any_modified(&RawMask::TYPE));
+        assert!(!fieldset.is_any_modified(&RawMask::VALUE));
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    #[fieldset(codec)]
+    struct Gps {
+        latitude: f32,
+        satellites: u32,
+        #[fieldset_skip]
+        raw: f32,
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    #[fieldset(codec)]
+    struct Telemetry {
+        altitude: f32,
+        #[fieldset]
+        gps: Gps,
+        ticks: u32,
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    #[fieldset(codec)]
+    struct Led(u8, #[fieldset(name = level)] u8);
+
+    #[derive(Clone, Copy, FieldSet)]
+    #[fieldset(codec)]
+    struct Panel(#[fieldset] Led, #[fieldset_skip] u32, bool);
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Marker;
+
+    impl FieldCodec for Marker {
+        const SIZE: usize = 0;
+
+        fn encode(&self, _buf: &mut [u8]) {}
+
+        fn decode(_buf: &[u8]) -> Option<Self> {
+            Some(Marker)
+        }
+    }
+
+    #[test]
+    pub fn codec_field_set_check() {
+        let e1 = TelemetryFieldType::Altitude(1.5);
+        let e2 = TelemetryFieldType::Gps(GpsFieldType::Latitude(5.0));
+        let e3 = TelemetryFieldType::Gps(GpsFieldType::Satellites(300));
+        let mut buf = [0; 5];
+        assert_eq!(e3.encode(&mut buf), Ok(5));
+        assert_eq!(buf, [2, 44, 1, 0, 0]);
+        assert_eq!(TelemetryFieldType::decode(&buf), Ok((e3, 5)));
+
+        [Marker; 3].encode(&mut []);
+        assert_eq!(<[Marker; 3]>::decode(&[]), Some([Marker; 3]));
+
+        let mut fieldset = TelemetryPerfFieldSet::new();
+        fieldset.apply(e3);
+        fieldset.apply(e1);
+        fieldset.apply(e2);
+        let mut buf = [0; 32];
+        let mut len = 0;
+        for field in fieldset.iter() {
+            len += field.encode(&mut buf[len..]).unwrap();
+        }
+        assert_eq!(len, 15);
+        let mut decoded = TelemetryPerfFieldSet::new();
+        let mut position = 0;
+        while position < len {
+            let (field, field_len) = TelemetryFieldType::decode(&buf[position..len]).unwrap();
+            decoded.apply(field);
+            position += field_len;
+        }
+        assert_eq!(
+            decoded.into_iter().collect::<Vec3<_>>(),
+            [Some(e3), Some(e1), Some(e2)]
+        );
+
+        assert_eq!(e3.encode(&mut [0; 4]), Err(CodecError::BufferTooShort));
+        assert_eq!(
+            TelemetryFieldType::decode(&[]),
+            Err(CodecError::BufferTooShort)
+        );
+        assert_eq!(
+            TelemetryFieldType::decode(&[2, 44]),
+            Err(CodecError::BufferTooShort)
+        );
+        assert_eq!(
+            TelemetryFieldType::decode(&[4, 0]),
+            Err(CodecError::InvalidTag(4))
+        );
+
+        let mut buf = [0; 2];
+        assert_eq!(encode_tag(300, &mut buf), Ok(2));
+        assert_eq!(buf, [0xac, 0x02]);
+        assert_eq!(decode_tag(&buf), Ok((300, 2)));
+
+        let level = PanelFieldType::_0(LedFieldType::Level(3));
+        assert_eq!(level.encode(&mut buf), Ok(2));
+        assert_eq!(buf, [1, 3]);
+        assert_eq!(PanelFieldType::decode(&buf).unwrap().0, level);
+        assert_eq!(
+            PanelFieldType::decode(&[2, 1]).unwrap().0,
+            PanelFieldType::_2(true)
+        );
+        assert_eq!(
+            PanelFieldType::decode(&[2, 2]),
+            Err(CodecError::InvalidValue)
+        );
+    }
+
+    #[cfg(feature = "critical-section")]
+    #[test]
+    pub fn shared_field_set_check() {
+        static SHARED: SharedFieldSet<MachinePerfFieldSet> =
+            SharedFieldSet::new(MachinePerfFieldSet::new(), MachinePerfFieldSet::new());
+
+        fn interrupt(count: u32) {
+            let mut setter = &SHARED;
+            setter.count().set(count);
+        }
+
+        assert!(SHARED.take().unwrap().is_empty());
+        interrupt(1);
+        (&SHARED).mode().variant().set(Mode::Fault(2));
+        interrupt(3);
+        assert_eq!(SHARED.with(|fieldset| fieldset.len()), 2);
+        let mut batch = SHARED.take().unwrap();
+        assert!(SHARED.take().is_none());
+        interrupt(4);
+        assert_eq!(
+            batch.drain().collect::<Vec3<_>>(),
+            [
+                Some(MachineFieldType::Count(3)),
+                Some(MachineFieldType::Mode(ModeFieldType::Variant(Mode::Fault(
+                    2
+                )))),
+                None
+            ]
+        );
+        drop(batch);
+        assert_eq!(
+            SHARED.take().unwrap().iter().cloned().collect::<Vec3<_>>(),
+            [Some(MachineFieldType::Count(4)), None, None]
+        );
+        assert!(SHARED.take().unwrap().is_empty());
+
+        // A sentinel past the pending modifications survives the reset of the dropped batch.
+        let sentinel = Some(MachineFieldType::Count(6));
+        interrupt(5);
+        let mut batch = SHARED.take().unwrap();
+        let fieldset: &mut MachinePerfFieldSet = &mut batch;
+        let last = fieldset.fields.len() - 1;
+        fieldset.fields[last] = sentinel;
+        drop(batch);
+        let batch = SHARED.take().unwrap();
+        SHARED.with(|fieldset| {
+            assert!(fieldset.is_empty());
+            assert_eq!(fieldset.fields[last], sentinel);
+        });
+        drop(batch);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    pub fn watch_field_set_check() {
+        use core::future::Future;
+        use core::pin::Pin;
+        use core::task::{Context, Poll, Waker};
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let model = Machine {
+            mode: Mode::Idle,
+            count: 0,
+        };
+        let watch = Watch::<Machine, MachinePerfFieldSet, 2>::new(model);
+        let mut any = watch.receiver().unwrap();
+        let mut counts = watch.receiver().unwrap();
+        assert!(watch.receiver().is_none());
+
+        let mut changed = any.changed();
+        assert_eq!(Pin::new(&mut changed).poll(&mut cx), Poll::Pending);
+        let mut count = counts.next(|batch: &MachinePerfFieldSet| {
+            if batch.is_any_modified(&MachineMask::COUNT) {
+                batch.pending().count().copied()
+            } else {
+                None
+            }
+        });
+        assert_eq!(Pin::new(&mut count).poll(&mut cx), Poll::Pending);
+
+        watch.commit(|setter| setter.mode().variant().set(Mode::Fault(1)));
+        assert_eq!(Pin::new(&mut changed).poll(&mut cx), Poll::Ready(()));
+        assert_eq!(Pin::new(&mut count).poll(&mut cx), Poll::Pending);
+        assert_eq!(watch.model(|model| model.mode), Mode::Fault(1));
+
+        watch.commit(|setter| {
+            setter.count().set(2);
+            setter.count().set(watch.model(|model| model.count) + 3);
+        });
+        assert_eq!(Pin::new(&mut count).poll(&mut cx), Poll::Ready(3));
+        assert_eq!(watch.model(|model| model.count), 3);
+        assert_eq!(watch.batch(|batch| batch.len()), 1);
+
+        watch.commit(|setter| setter.count().set(4));
+        watch.commit(|setter| setter.mode().variant().set(Mode::Idle));
+        let mut count = counts.next(|batch: &MachinePerfFieldSet| batch.pending().count().copied());
+        assert_eq!(Pin::new(&mut count).poll(&mut cx), Poll::Ready(4));
+        assert_eq!(watch.batch(|batch| batch.len()), 1);
+
+        drop(counts);
+        assert!(watch.receiver().is_some());
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    #[cfg_attr(feature = "serde", fieldset(serde))]
+    struct Imu<T: Copy> {
+        accel: [T; 3],
+        temperature: f32,
+    }
+
+    #[derive(Clone, Copy, FieldSet)]
+    #[cfg_attr(feature = "serde", fieldset(serde))]
+    struct Report<'a> {
+        name: &'a str,
+        #[fieldset]
+        imu: Imu<i16>,
+        armed: bool,
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn serde_field_set_check() {
+        let e2 = ReportFieldType::Imu(ImuFieldType::Accel([1, 2, 3]));
+        let e3 = ReportFieldType::Imu(ImuFieldType::Temperature(20.5));
+        let e4 = ReportFieldType::Armed(true);
+        let json = r#"{"imu":{"temperature":20.5}}"#;
+        assert_eq!(serde_json::to_string(&e3).unwrap(), json);
+        assert_eq!(serde_json::from_str::<ReportFieldType>(json).unwrap(), e3);
+
+        let json = r#"{"imu":{"accel":[1,2,3]},"armed":true}"#;
+        let mut opt_fieldset = ReportOptFieldSet::new();
+        opt_fieldset.apply(e4);
+        opt_fieldset.apply(e2);
+        assert_eq!(serde_json::to_string(&opt_fieldset).unwrap(), json);
+        let opt_fieldset: ReportOptFieldSet = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            opt_fieldset.into_iter().collect::<Vec3<_>>(),
+            [Some(e2), Some(e4), None]
+        );
+
+        let mut perf_fieldset = ReportPerfFieldSet::new();
+        perf_fieldset.apply(e4);
+        perf_fieldset.apply(e2);
+        assert_eq!(serde_json::to_string(&perf_fieldset).unwrap(), json);
+        let perf_fieldset: ReportPerfFieldSet = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            perf_fieldset.into_iter().collect::<Vec3<_>>(),
+            [Some(e2), Some(e4), None]
+        );
+
+        assert_eq!(
+            serde_json::to_string(&ReportPerfFieldSet::new()).unwrap(),
+            "{}"
+        );
+        assert!(serde_json::from_str::<ReportOptFieldSet>(r#"{"speed":9}"#).is_err());
+        assert!(serde_json::from_str::<ReportFieldType>(r#"{"imu":{"speed":9}}"#).is_err());
+
+        let json = r#"{"name":"imu-1"}"#;
+        let opt_fieldset: ReportOptFieldSet = serde_json::from_str(json).unwrap();
+        assert_eq!(opt_fieldset.pending().name(), Some(&"imu-1"));
+        assert_eq!(
+            serde_json::to_string(&ImuFieldType::<u8>::Accel([1, 2, 3])).unwrap(),
+            r#"{"accel":[1,2,3]}"#
+        );
+
+        let json = r#"{"type":4}"#;
+        let mut perf_fieldset = RawPerfFieldSet::new();
+        perf_fieldset.r#type().set(4);
+        assert_eq!(serde_json::to_string(&perf_fieldset).unwrap(), json);
+        let opt_fieldset: RawOptFieldSet = serde_json::from_str(json).unwrap();
+        assert_eq!(serde_json::to_string(&opt_fieldset).unwrap(), json);
+        assert_eq!(
+            serde_json::from_str::<RawFieldType>(json).unwrap(),
+            RawFieldType::RType(4)
+        );
     }
 }
@@ -0,0 +1,150 @@
+//! Append-only log of modifications with a fixed capacity and an overflow policy.
+
+use core::iter::Chain;
+
+use crate::{Drain, FieldClear, FieldSink, Iter};
+
+/// What a full `LogFieldSet` does with a new modification.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogOverflow {
+    /// The oldest modification is discarded to make room for the new one, and counted by
+    /// `LogFieldSet::dropped`.
+    DropOldest,
+    /// The new modification is discarded, and counted by `LogFieldSet::dropped`.
+    DropNewest,
+    /// The new modification is discarded and reported by `LogFieldSet::check`.
+    Error,
+}
+
+/// Error of a `LogFieldSet` that rejected modifications under `LogOverflow::Error`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LogFull;
+
+/// Append-only log of every modification in the order it was made, for audit trails and replay.
+///
+/// It holds up to `N` modifications of any model with `FieldType` `T` and implements the derived
+/// `FieldSetter` traits like the other `FieldSet` types. Unlike `BitFieldSet` and `PerfFieldSet`,
+/// a field modified several times is logged every time.
+#[derive(Clone, Debug)]
+pub struct LogFieldSet<T, const N: usize> {
+    /// Ring buffer of the logged modifications, starting from the oldest at `head`.
+    fields: [Option<T>; N],
+    head: usize,
+    len: usize,
+    overflow: LogOverflow,
+    /// Number of modifications discarded by the overflow policy since the last drain.
+    dropped: usize,
+}
+
+impl<T, const N: usize> LogFieldSet<T, N> {
+    pub fn new(overflow: LogOverflow) -> Self {
+        Self {
+            fields: [(); N].map(|_| None),
+            head: 0,
+            len: 0,
+            overflow,
+            dropped: 0,
+        }
+    }
+
+    /// Logs `field`, following the overflow policy once the log is full.
+    pub fn push(&mut self, field: T) -> Result<(), LogFull> {
+        if self.len == N {
+            self.dropped += 1;
+            match self.overflow {
+                LogOverflow::DropOldest if N > 0 => {
+                    self.fields[self.head] = Some(field);
+                    self.head = (self.head + 1) % N;
+                    return Ok(());
+                }
+                LogOverflow::DropOldest | LogOverflow::DropNewest => return Ok(()),
+                LogOverflow::Error => return Err(LogFull),
+            }
+        }
+        self.fields[(self.head + self.len) % N] = Some(field);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Fails if modifications were rejected since the log was last drained or cleared.
+    pub fn check(&self) -> Result<(), LogFull> {
+        if self.overflow == LogOverflow::Error && self.dropped > 0 {
+            Err(LogFull)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Number of modifications discarded by the overflow policy since the log was last drained or
+    /// cleared.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn iter(&self) -> Chain<Iter<'_, T>, Iter<'_, T>> {
+        let (wrapped, oldest) = self.fields.split_at(self.head);
+        let oldest_len = self.len.min(oldest.len());
+        Iter::new(&oldest[..oldest_len]).chain(Iter::new(&wrapped[..self.len - oldest_len]))
+    }
+
+    pub fn drain(&mut self) -> Chain<Drain<'_, T>, Drain<'_, T>> {
+        self.dropped = 0;
+        let head = core::mem::take(&mut self.head);
+        let (wrapped, oldest) = self.fields.split_at_mut(head);
+        let len = core::mem::take(&mut self.len);
+        let oldest_len = len.min(oldest.len());
+        Drain::new(&mut oldest[..oldest_len]).chain(Drain::new(&mut wrapped[..len - oldest_len]))
+    }
+
+    pub fn clear(&mut self) {
+        self.drain().for_each(drop);
+    }
+}
+
+impl<T, const N: usize> FieldClear for LogFieldSet<T, N> {
+    fn clear(&mut self) {
+        LogFieldSet::clear(self)
+    }
+}
+
+impl<T, const N: usize> FieldSink<T> for LogFieldSet<T, N> {
+    fn put(&mut self, _index: usize, value: T) {
+        // Rejections are reported by `check`, as setters have no error path.
+        let _ = self.push(value);
+    }
+}
+
+/// Iterator over the modifications of a `LogFieldSet`, in the order they were made.
+#[derive(Clone, Debug)]
+pub struct LogFieldSetIter<T, const N: usize>(core::array::IntoIter<Option<T>, N>);
+
+impl<T, const N: usize> Iterator for LogFieldSetIter<T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Logged modifications are stored contiguously from the start of the array.
+        self.0.next().flatten()
+    }
+}
+
+impl<T, const N: usize> IntoIterator for LogFieldSet<T, N> {
+    type Item = T;
+    type IntoIter = LogFieldSetIter<T, N>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        self.fields.rotate_left(self.head);
+        LogFieldSetIter(self.fields.into_iter())
+    }
+}
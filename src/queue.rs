@@ -0,0 +1,192 @@
+//! Lock-free single-producer single-consumer queue of modifications, for streaming them between
+//! an interrupt handler and a task.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::FieldSink;
+
+/// Ring buffer of up to `N` modifications, split into a `Producer` that implements the derived
+/// `FieldSetter` traits and a `Consumer` that iterates the modifications in the order they were
+/// made.
+///
+/// Only atomic loads and stores are used, so it also works on targets without compare-and-swap.
+pub struct ChangeQueue<T, const N: usize> {
+    /// Position of the next read, modulo `2 * N` to tell a full queue from an empty one. Only
+    /// stored by the consumer.
+    head: AtomicUsize,
+    /// Position of the next write, modulo `2 * N`. Only stored by the producer.
+    tail: AtomicUsize,
+    /// Number of modifications dropped by the setters, only stored by the producer.
+    dropped: AtomicUsize,
+    /// Whether the queue was split through `&'static self`, which can happen only once.
+    split: AtomicBool,
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+}
+
+// SAFETY: Each slot is only accessed by the producer before it is published through `tail` and by
+// the consumer after, and until it is released through `head`.
+unsafe impl<T: Send, const N: usize> Sync for ChangeQueue<T, N> {}
+
+impl<T, const N: usize> ChangeQueue<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+            split: AtomicBool::new(false),
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+        }
+    }
+
+    pub fn split(&mut self) -> (Producer<'_, T, N>, Consumer<'_, T, N>) {
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        (
+            Producer { queue: self },
+            Consumer {
+                queue: self,
+                dropped,
+            },
+        )
+    }
+
+    /// Splits a queue in a `static`, such as for an interrupt handler and a task, or returns
+    /// `None` if it was already split.
+    ///
+    /// Unlike the rest of the queue, this requires compare-and-swap.
+    #[cfg(target_has_atomic = "8")]
+    pub fn split_static(
+        &'static self,
+    ) -> Option<(Producer<'static, T, N>, Consumer<'static, T, N>)> {
+        if self.split.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        Some((
+            Producer { queue: self },
+            Consumer {
+                queue: self,
+                dropped,
+            },
+        ))
+    }
+
+    fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        if tail >= head {
+            tail - head
+        } else {
+            tail + 2 * N - head
+        }
+    }
+
+    fn advance(position: usize) -> usize {
+        if position + 1 == 2 * N {
+            0
+        } else {
+            position + 1
+        }
+    }
+}
+
+impl<T, const N: usize> Default for ChangeQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for ChangeQueue<T, N> {
+    fn drop(&mut self) {
+        Consumer {
+            queue: self,
+            dropped: 0,
+        }
+        .for_each(drop);
+    }
+}
+
+/// Writing half of a `ChangeQueue`.
+pub struct Producer<'a, T, const N: usize> {
+    queue: &'a ChangeQueue<T, N>,
+}
+
+impl<'a, T, const N: usize> Producer<'a, T, N> {
+    /// Appends `field`, or returns it if the queue is full.
+    pub fn push(&mut self, field: T) -> Result<(), T> {
+        if self.queue.len() == N {
+            return Err(field);
+        }
+        let tail = self.queue.tail.load(Ordering::Relaxed);
+        // SAFETY: The slot was released by the consumer and is not published yet.
+        unsafe { (*self.queue.slots[tail % N].get()).write(field) };
+        self.queue
+            .tail
+            .store(ChangeQueue::<T, N>::advance(tail), Ordering::Release);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'a, T, const N: usize> FieldSink<T> for Producer<'a, T, N> {
+    fn put(&mut self, _index: usize, value: T) {
+        // Setters have no error path, so overflows are counted for the consumer instead.
+        if self.push(value).is_err() {
+            let dropped = self.queue.dropped.load(Ordering::Relaxed);
+            self.queue
+                .dropped
+                .store(dropped.wrapping_add(1), Ordering::Relaxed);
+        }
+    }
+}
+
+/// Reading half of a `ChangeQueue`, iterating the modifications as they are made.
+///
+/// The iterator returns `None` whenever the queue is empty and resumes once the producer makes
+/// more modifications.
+pub struct Consumer<'a, T, const N: usize> {
+    queue: &'a ChangeQueue<T, N>,
+    dropped: usize,
+}
+
+impl<'a, T, const N: usize> Consumer<'a, T, N> {
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of modifications that the setters dropped because the queue was full, since the
+    /// last call.
+    pub fn take_dropped(&mut self) -> usize {
+        let dropped = self.queue.dropped.load(Ordering::Relaxed);
+        dropped.wrapping_sub(core::mem::replace(&mut self.dropped, dropped))
+    }
+}
+
+impl<'a, T, const N: usize> Iterator for Consumer<'a, T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let head = self.queue.head.load(Ordering::Relaxed);
+        if head == self.queue.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        // SAFETY: The slot was published by the producer and is not released yet.
+        let field = unsafe { (*self.queue.slots[head % N].get()).assume_init_read() };
+        self.queue
+            .head
+            .store(ChangeQueue::<T, N>::advance(head), Ordering::Release);
+        Some(field)
+    }
+}
@@ -0,0 +1,169 @@
+//! Runtime support of the serde implementations derived with the `serde` feature.
+//!
+//! `FieldType` is externally tagged with the field name, while the `FieldSet` storages are
+//! serialized as sparse maps from field names to values, nested for `#[fieldset]` children.
+
+use core::fmt;
+use core::marker::PhantomData;
+
+pub use serde;
+use serde::de::{
+    self, DeserializeSeed, Deserializer, EnumAccess, MapAccess, VariantAccess, Visitor,
+};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::{FieldLookup, Pending};
+
+struct FieldName(&'static [&'static str]);
+
+impl<'de> Visitor<'de> for FieldName {
+    type Value = usize;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a field name")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<usize, E> {
+        match usize::try_from(value) {
+            Ok(field) if field < self.0.len() => Ok(field),
+            _ => Err(E::invalid_value(de::Unexpected::Unsigned(value), &self)),
+        }
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<usize, E> {
+        self.0
+            .iter()
+            .position(|name| *name == value)
+            .ok_or_else(|| E::unknown_field(value, self.0))
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<usize, E> {
+        match core::str::from_utf8(value) {
+            Ok(value) => self.visit_str(value),
+            Err(_) => Err(E::invalid_value(de::Unexpected::Bytes(value), &self)),
+        }
+    }
+}
+
+/// Map keys are plain strings, so they are not deserialized as identifiers that some formats
+/// encode as indices.
+struct FieldKey(&'static [&'static str]);
+
+impl<'de> DeserializeSeed<'de> for FieldKey {
+    type Value = usize;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+        deserializer.deserialize_str(FieldName(self.0))
+    }
+}
+
+struct FieldVariant(&'static [&'static str]);
+
+impl<'de> DeserializeSeed<'de> for FieldVariant {
+    type Value = usize;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+        deserializer.deserialize_identifier(FieldName(self.0))
+    }
+}
+
+/// Implemented by the derived `FieldType` to deserialize the payload of the field at position
+/// `field` of `NAMES`.
+pub trait DeserializeFieldType<'de>: Sized {
+    const NAME: &'static str;
+    const NAMES: &'static [&'static str];
+
+    fn deserialize_variant<A: VariantAccess<'de>>(
+        field: usize,
+        variant: A,
+    ) -> Result<Self, A::Error>;
+}
+
+struct FieldTypeVisitor<T>(PhantomData<fn() -> T>);
+
+impl<'de, T: DeserializeFieldType<'de>> Visitor<'de> for FieldTypeVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "enum {}", T::NAME)
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<T, A::Error> {
+        let (field, variant) = data.variant_seed(FieldVariant(T::NAMES))?;
+        T::deserialize_variant(field, variant)
+    }
+}
+
+pub fn deserialize_field_type<'de, D: Deserializer<'de>, T: DeserializeFieldType<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    deserializer.deserialize_enum(T::NAME, T::NAMES, FieldTypeVisitor(PhantomData))
+}
+
+/// Implemented by the derived `OptFieldSet` to deserialize the value of the field at position
+/// `field` of `NAMES`.
+pub trait DeserializeFields<'de>: Default {
+    const NAMES: &'static [&'static str];
+
+    fn deserialize_field<A: MapAccess<'de>>(
+        &mut self,
+        field: usize,
+        map: &mut A,
+    ) -> Result<(), A::Error>;
+}
+
+struct FieldsVisitor<T>(PhantomData<fn() -> T>);
+
+impl<'de, T: DeserializeFields<'de>> Visitor<'de> for FieldsVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of field modifications")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<T, A::Error> {
+        let mut fields = T::default();
+        while let Some(field) = map.next_key_seed(FieldKey(T::NAMES))? {
+            fields.deserialize_field(field, &mut map)?;
+        }
+        Ok(fields)
+    }
+}
+
+pub fn deserialize_fields<'de, D: Deserializer<'de>, T: DeserializeFields<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    deserializer.deserialize_map(FieldsVisitor(PhantomData))
+}
+
+/// Implemented by the derived `FieldType` to serialize the pending modifications of a `Pending`
+/// view as map entries.
+pub trait SerializeFields: Sized {
+    fn serialized_len<'a, P: FieldLookup<'a, Self>>(pending: &Pending<P>) -> usize
+    where
+        Self: 'a;
+
+    fn serialize_fields<'a, P: FieldLookup<'a, Self>, M: SerializeMap>(
+        pending: &Pending<P>,
+        map: &mut M,
+    ) -> Result<(), M::Error>
+    where
+        Self: 'a;
+}
+
+/// Sparse map of the pending modifications of a `Pending` view.
+pub struct SerializePending<P, T>(Pending<P>, PhantomData<fn() -> T>);
+
+impl<P, T> SerializePending<P, T> {
+    pub fn new(pending: Pending<P>) -> Self {
+        Self(pending, PhantomData)
+    }
+}
+
+impl<'a, P: FieldLookup<'a, T>, T: SerializeFields + 'a> Serialize for SerializePending<P, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(T::serialized_len(&self.0)))?;
+        T::serialize_fields(&self.0, &mut map)?;
+        map.end()
+    }
+}
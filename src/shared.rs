@@ -0,0 +1,116 @@
+//! Storage shared between interrupt handlers and the main loop, enabled by the
+//! `critical-section` feature.
+
+use core::cell::{Cell, RefCell, RefMut};
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+
+use critical_section::Mutex;
+
+use crate::{FieldClear, FieldSink};
+
+/// `FieldSet` storage that can be modified through `&self`, such as from an interrupt handler and
+/// the main loop at once, with every access guarded by a critical section.
+///
+/// `&SharedFieldSet` implements the derived `FieldSetter` traits, and the consumer swaps the
+/// pending batch out with `take()`. The set holds two preallocated storages `S`: the front one
+/// records modifications, while the back one is handed to the consumer. A critical section
+/// therefore only lasts for a single modification or swap, and the taken storage is reset outside
+/// of it.
+pub struct SharedFieldSet<S> {
+    buffers: [RefCell<S>; 2],
+    /// Index of the front buffer.
+    front: Mutex<Cell<usize>>,
+    /// Whether the back buffer is held by a `SharedBatch`.
+    taken: Mutex<Cell<bool>>,
+}
+
+// SAFETY: The `RefCell`s are the only state not guarded by a `Mutex`. The front buffer is only
+// borrowed within a critical section, which excludes every other access to it. The back buffer
+// is only borrowed by the single `SharedBatch` that holds it: `take` sets `taken` and swaps the
+// front index within the same critical section, so no other `SharedBatch` can be created and no
+// setter can reach the buffer until the batch releases it and clears `taken`. `S: Send` allows
+// the buffers to be modified from the context that holds them.
+unsafe impl<S: Send> Sync for SharedFieldSet<S> {}
+
+impl<S> SharedFieldSet<S> {
+    /// Creates the set from two empty storages, such as `DomainModelPerfFieldSet::new()`, so it
+    /// can be created in a `static`.
+    pub const fn new(front: S, back: S) -> Self {
+        Self {
+            buffers: [RefCell::new(front), RefCell::new(back)],
+            front: Mutex::new(Cell::new(0)),
+            taken: Mutex::new(Cell::new(false)),
+        }
+    }
+
+    /// Calls `f` with the pending batch within a critical section.
+    pub fn with<T>(&self, f: impl FnOnce(&mut S) -> T) -> T {
+        critical_section::with(|cs| f(&mut self.buffers[self.front.borrow(cs).get()].borrow_mut()))
+    }
+
+    /// Swaps out the pending batch, leaving the set empty, or returns `None` if the previously
+    /// taken batch is still held.
+    pub fn take(&self) -> Option<SharedBatch<'_, S>>
+    where
+        S: FieldClear,
+    {
+        let back = critical_section::with(|cs| {
+            if self.taken.borrow(cs).replace(true) {
+                return None;
+            }
+            let front = self.front.borrow(cs);
+            let back = front.get();
+            front.set(1 - back);
+            Some(back)
+        })?;
+        Some(SharedBatch {
+            set: self,
+            fields: ManuallyDrop::new(self.buffers[back].borrow_mut()),
+        })
+    }
+}
+
+impl<S: Default> Default for SharedFieldSet<S> {
+    fn default() -> Self {
+        Self::new(S::default(), S::default())
+    }
+}
+
+impl<T, S: FieldSink<T>> FieldSink<T> for &SharedFieldSet<S> {
+    fn put(&mut self, index: usize, value: T) {
+        self.with(|fields| fields.put(index, value))
+    }
+}
+
+/// Batch taken out of a `SharedFieldSet`, which is reset and handed back to the set when dropped.
+pub struct SharedBatch<'a, S: FieldClear> {
+    set: &'a SharedFieldSet<S>,
+    fields: ManuallyDrop<RefMut<'a, S>>,
+}
+
+impl<'a, S: FieldClear> Deref for SharedBatch<'a, S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.fields
+    }
+}
+
+impl<'a, S: FieldClear> DerefMut for SharedBatch<'a, S> {
+    fn deref_mut(&mut self) -> &mut S {
+        &mut self.fields
+    }
+}
+
+impl<'a, S: FieldClear> Drop for SharedBatch<'a, S> {
+    fn drop(&mut self) {
+        // The derived `BitFieldSet`, `PerfFieldSet` and `IndexFieldSet` only reset the slots of
+        // the pending modifications.
+        self.fields.clear();
+        // SAFETY: `fields` is not used after this point, and the `RefMut` is released before
+        // `taken` is cleared, so before the set can swap the buffer to the front again.
+        unsafe { ManuallyDrop::drop(&mut self.fields) };
+        critical_section::with(|cs| self.set.taken.borrow(cs).set(false));
+    }
+}
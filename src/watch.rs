@@ -0,0 +1,173 @@
+//! Async change stream over a model, enabled by the `async` feature.
+//!
+//! Receivers are woken through their wakers only, so the cell works with any executor without
+//! alloc. It is not `Sync` and is meant to be shared by the tasks of a single-threaded executor.
+
+use core::cell::RefCell;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use crate::{FieldSetModel, FieldSink};
+
+struct State<M, S, const R: usize> {
+    model: M,
+    batch: S,
+    version: usize,
+    wakers: [Option<Waker>; R],
+    receivers: [bool; R],
+    /// Modifications committed since each receiver last polled.
+    pending: [S; R],
+}
+
+/// Cell that owns a model along with the last batch of modifications committed to it, and
+/// notifies up to `R` receivers of every new batch.
+///
+/// The batch is stored in `S`, usually the `PerfFieldSet` of the model. Without alloc, each
+/// receiver holds one of `R` fixed slots for its waker and the modifications it has not seen yet,
+/// which it frees when dropped.
+pub struct Watch<M, S, const R: usize> {
+    state: RefCell<State<M, S, R>>,
+}
+
+impl<M: FieldSetModel, S, const R: usize> Watch<M, S, R>
+where
+    S: FieldSink<M::FieldType> + IntoIterator<Item = M::FieldType> + Default,
+    M: FieldSink<M::FieldType>,
+    M::FieldType: Clone,
+{
+    pub fn new(model: M) -> Self {
+        Self {
+            state: RefCell::new(State {
+                model,
+                batch: S::default(),
+                version: 0,
+                wakers: [(); R].map(|_| None),
+                receivers: [false; R],
+                pending: [(); R].map(|_| S::default()),
+            }),
+        }
+    }
+
+    /// Records the modifications made by `f` through the derived `FieldSetter` traits as a new
+    /// batch, applies them to the model and wakes the receivers.
+    pub fn commit<T>(&self, f: impl FnOnce(&mut S) -> T) -> T {
+        // The batch is recorded before the state is borrowed, so `f` can read the cell.
+        let mut batch = S::default();
+        let result = f(&mut batch);
+        let wakers = {
+            let mut state = self.state.borrow_mut();
+            let state = &mut *state;
+            state.batch = S::default();
+            for field in batch {
+                let index = M::field_index(&field);
+                for (pending, used) in state.pending.iter_mut().zip(state.receivers) {
+                    if used {
+                        pending.put(index, field.clone());
+                    }
+                }
+                state.batch.put(index, field.clone());
+                state.model.put(index, field);
+            }
+            state.version = state.version.wrapping_add(1);
+            core::mem::replace(&mut state.wakers, [(); R].map(|_| None))
+        };
+        // Wakers are called once the state is released, in case they poll the receivers.
+        for waker in wakers.into_iter().flatten() {
+            waker.wake();
+        }
+        result
+    }
+
+    /// Returns a new receiver of the batches committed from now on, or `None` if all `R`
+    /// receivers are in use.
+    pub fn receiver(&self) -> Option<Receiver<'_, M, S, R>> {
+        let mut state = self.state.borrow_mut();
+        let slot = state.receivers.iter().position(|used| !used)?;
+        state.receivers[slot] = true;
+        state.pending[slot] = S::default();
+        Some(Receiver {
+            watch: self,
+            slot,
+            version: state.version,
+        })
+    }
+
+    pub fn model<T>(&self, f: impl FnOnce(&M) -> T) -> T {
+        f(&self.state.borrow().model)
+    }
+
+    /// Calls `f` with the last committed batch.
+    pub fn batch<T>(&self, f: impl FnOnce(&S) -> T) -> T {
+        f(&self.state.borrow().batch)
+    }
+}
+
+/// Receiver of the batches committed to a `Watch`.
+///
+/// Each receiver is handed the modifications committed since it last polled, merged into a
+/// single `S`, so batches committed while it is not awaiting are not missed.
+pub struct Receiver<'a, M, S, const R: usize> {
+    watch: &'a Watch<M, S, R>,
+    slot: usize,
+    version: usize,
+}
+
+impl<'a, M, S, const R: usize> Receiver<'a, M, S, R> {
+    /// Waits for the next modifications for which `f` returns a value, such as
+    /// `|batch| batch.pending().sub().b().copied()` or
+    /// `|batch| batch.is_any_modified(&MASK).then_some(())`.
+    pub fn next<T, F: FnMut(&S) -> Option<T> + Unpin>(&mut self, f: F) -> Next<'_, 'a, M, S, F, R> {
+        Next { receiver: self, f }
+    }
+
+    /// Waits for the next commit.
+    pub fn changed(&mut self) -> Next<'_, 'a, M, S, impl FnMut(&S) -> Option<()> + Unpin, R> {
+        self.next(|_| Some(()))
+    }
+}
+
+impl<'a, M, S, const R: usize> Drop for Receiver<'a, M, S, R> {
+    fn drop(&mut self) {
+        let mut state = self.watch.state.borrow_mut();
+        state.receivers[self.slot] = false;
+        state.wakers[self.slot] = None;
+    }
+}
+
+/// Future returned by `Receiver::next`.
+pub struct Next<'r, 'a, M, S, F, const R: usize> {
+    receiver: &'r mut Receiver<'a, M, S, R>,
+    f: F,
+}
+
+impl<'r, 'a, M, S, T, F, const R: usize> Future for Next<'r, 'a, M, S, F, R>
+where
+    S: Default,
+    F: FnMut(&S) -> Option<T> + Unpin,
+{
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let this = self.get_mut();
+        let receiver = &mut *this.receiver;
+        loop {
+            let mut state = receiver.watch.state.borrow_mut();
+            if state.version == receiver.version {
+                match &mut state.wakers[receiver.slot] {
+                    Some(waker) if waker.will_wake(cx.waker()) => {}
+                    waker => *waker = Some(cx.waker().clone()),
+                }
+                return Poll::Pending;
+            }
+            receiver.version = state.version;
+            let pending = core::mem::take(&mut state.pending[receiver.slot]);
+            // The state is released while `f` runs, so it can use the cell, and the loop picks up
+            // what it commits.
+            drop(state);
+            if let Some(value) = (this.f)(&pending) {
+                return Poll::Ready(value);
+            }
+        }
+    }
+}